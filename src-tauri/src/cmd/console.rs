@@ -0,0 +1,157 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_dialog::DialogExt;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use super::instance::error_title;
+
+use crate::{
+    runner::{
+        console::{ConsoleLine, OutputStream},
+        Runner,
+    },
+    AppState,
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UiConsoleLine {
+    stream: UiOutputStream,
+    text: String,
+    time: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum UiOutputStream {
+    #[serde(rename = "stdout")]
+    Stdout,
+    #[serde(rename = "stderr")]
+    Stderr,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct UiConsoleEvent {
+    runner: String,
+    instance: String,
+    line: UiConsoleLine,
+}
+
+impl From<ConsoleLine> for UiConsoleLine {
+    fn from(value: ConsoleLine) -> Self {
+        Self {
+            stream: match value.stream {
+                OutputStream::Stdout => UiOutputStream::Stdout,
+                OutputStream::Stderr => UiOutputStream::Stderr,
+            },
+            text: value.text,
+            time: value.time,
+        }
+    }
+}
+
+/// Starts streaming the console of an instance, returning the history
+#[tauri::command]
+pub async fn console_open(app: AppHandle, runner: String, instance: String) -> Result<Vec<UiConsoleLine>, String> {
+    let state = app.state::<AppState>();
+
+    let runner = state.runners.lock().await.get(&runner).cloned();
+
+    match runner {
+        Some(runner) => {
+            match runner.open_console(&instance).await {
+                Ok(lines) => Ok(lines.into_iter().map(|l| l.into()).collect()),
+                Err(e) => {
+                    app.dialog()
+                        .message(e.to_string())
+                        .title("Console Error")
+                        .show(|_| {});
+
+                    Err(e.to_string())
+                }
+            }
+        }
+        None => Err("Runner not found".to_string()),
+    }
+}
+
+/// Stops streaming the console of an instance
+#[tauri::command]
+pub async fn console_close(app: AppHandle, runner: String, instance: String) -> Result<(), String> {
+    let state = app.state::<AppState>();
+
+    let runner = state.runners.lock().await.get(&runner).cloned();
+
+    match runner {
+        Some(runner) => {
+            runner.close_console(&instance).await;
+
+            Ok(())
+        }
+        None => Err("Runner not found".to_string()),
+    }
+}
+
+/// Sends a line of input to the stdin of an instance
+#[tauri::command]
+pub async fn send_instance_input(app: AppHandle, runner: String, instance: String, input: String) -> Result<(), String> {
+    let state = app.state::<AppState>();
+
+    let runner = state.runners.lock().await.get(&runner).cloned();
+
+    match runner {
+        Some(runner) => {
+            match runner.send_instance_input(&instance, &input).await {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    app.dialog()
+                        .message(e.to_string())
+                        .title(error_title(&e))
+                        .show(|_| {});
+
+                    Err(e.to_string())
+                }
+            }
+        }
+        None => Err("Runner not found".to_string()),
+    }
+}
+
+/// Returns the commands sent to an instance, oldest first
+#[tauri::command]
+pub async fn instance_input_history(app: AppHandle, runner: String, instance: String) -> Result<Vec<String>, String> {
+    let state = app.state::<AppState>();
+
+    let runner = state.runners.lock().await.get(&runner).cloned();
+
+    match runner {
+        Some(runner) => Ok(runner.input_history(&instance).await),
+        None => Err("Runner not found".to_string()),
+    }
+}
+
+/// Emits console output of the runner's instances to the frontend
+pub async fn forward_console(app: Arc<AppHandle>, id: String, runner: Arc<Runner>) {
+    let mut rx = runner.subscribe_console();
+
+    let task = runner.spawn_cancellable(async move {
+        loop {
+            let (instance, line) = match rx.recv().await {
+                Ok(o) => o,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("Dropped {} console lines", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let _ = app.emit("console", UiConsoleEvent {
+                runner: id.clone(),
+                instance,
+                line: line.into(),
+            });
+        }
+    });
+
+    runner.attach_task(task).await;
+}
@@ -1,25 +1,80 @@
 use serde::{Deserialize, Serialize};
-use tauri::{AppHandle, Manager};
+use serde_json::Value;
+use std::{collections::HashMap, path::PathBuf};
+use tauri::{AppHandle, Emitter, Manager};
 use tauri_plugin_dialog::DialogExt;
+use tokio::sync::oneshot;
 
 use crate::{
-    runner::instance::{
-        Instance, InstanceRequest, InstanceStatus, InstanceType, VolkanicSource
-    }, AppState
+    runner::{
+        instance::{
+            Instance,
+            InstanceRequest,
+            InstanceStatus,
+            InstanceType,
+            PortMapping,
+            PortProtocol,
+            VolkanicSource,
+            VolumeMount,
+        },
+        upload::UploadProgress,
+        validate::{validate_request, ValidationReport},
+        Error,
+    },
+    AppState,
 };
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UiInstance {
     name: String,
+    #[serde(rename = "type")]
     inst_type: UiInstanceType,
     status: UiInstanceStatus,
+    /// JSON as sent by the runner
+    raw: Value,
+    /// Set if the console couldn't fully understand the instance
+    decode_error: Option<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub enum UiInstanceType {
     Volkanic {
         source: UiVolkanicSource,
-    }
+    },
+    Container {
+        image: String,
+        env: HashMap<String, String>,
+        ports: Vec<UiPortMapping>,
+        volumes: Vec<UiVolumeMount>,
+    },
+    /// Not known to this console, holds the type name
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiPortMapping {
+    host: u16,
+    container: u16,
+    protocol: UiPortProtocol,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum UiPortProtocol {
+    #[serde(rename = "tcp")]
+    Tcp,
+    #[serde(rename = "udp")]
+    Udp,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiVolumeMount {
+    source: String,
+    target: String,
+    read_only: bool,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -36,12 +91,30 @@ pub enum UiInstanceStatus {
     Starting,
     #[serde(rename = "stopping")]
     Stopping,
+    /// Not known to this console, holds the status name
+    #[serde(rename = "unknown")]
+    Unknown(String),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub enum UiVolkanicSource {
     Url(String),
     Base64(String),
+    /// ID of a file uploaded to the runner
+    Upload(String),
+    /// Not known to this console, holds the source name
+    Unknown(String),
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiUploadProgress {
+    runner: String,
+    /// Path of the file being uploaded
+    path: String,
+    sent: u64,
+    total: u64,
 }
 
 impl From<Instance> for UiInstance {
@@ -50,6 +123,8 @@ impl From<Instance> for UiInstance {
             name: value.name,
             inst_type: value.inst_type.into(),
             status: value.status.into(),
+            raw: value.raw,
+            decode_error: value.decode_error,
         }
     }
 }
@@ -60,6 +135,40 @@ impl From<InstanceType> for UiInstanceType {
             InstanceType::Volkanic { source } => {
                 UiInstanceType::Volkanic { source: source.into() }
             }
+            InstanceType::Container { image, env, ports, volumes } => {
+                UiInstanceType::Container {
+                    image,
+                    env,
+                    ports: ports.into_iter().map(|p| p.into()).collect(),
+                    volumes: volumes.into_iter().map(|v| v.into()).collect(),
+                }
+            }
+            InstanceType::Unknown(kind) => {
+                UiInstanceType::Unknown(kind)
+            }
+        }
+    }
+}
+
+impl From<PortMapping> for UiPortMapping {
+    fn from(value: PortMapping) -> Self {
+        Self {
+            host: value.host,
+            container: value.container,
+            protocol: match value.protocol {
+                PortProtocol::Tcp => UiPortProtocol::Tcp,
+                PortProtocol::Udp => UiPortProtocol::Udp,
+            },
+        }
+    }
+}
+
+impl From<VolumeMount> for UiVolumeMount {
+    fn from(value: VolumeMount) -> Self {
+        Self {
+            source: value.source,
+            target: value.target,
+            read_only: value.read_only,
         }
     }
 }
@@ -85,6 +194,9 @@ impl From<InstanceStatus> for UiInstanceStatus {
             InstanceStatus::Stopping => {
                 UiInstanceStatus::Stopping
             }
+            InstanceStatus::Unknown(status) => {
+                UiInstanceStatus::Unknown(status)
+            }
         }
     }
 }
@@ -98,10 +210,32 @@ impl From<VolkanicSource> for UiVolkanicSource {
             VolkanicSource::Base64(encoded) => {
                 UiVolkanicSource::Base64(encoded)
             }
+            VolkanicSource::Upload(id) => {
+                UiVolkanicSource::Upload(id)
+            }
+            VolkanicSource::Unknown(source) => {
+                UiVolkanicSource::Unknown(source)
+            }
         }
     }
 }
 
+/// Dialog title describing the kind of error
+pub fn error_title(e: &Error) -> &'static str {
+    match e {
+        Error::NotFound(_) => "Instance Not Found",
+        Error::Conflict(_) => "Instance Conflict",
+        Error::InvalidState(_) => "Invalid Instance State",
+        Error::Server(_) => "Runner Error",
+        Error::Unauthorized => "Unauthorized",
+        Error::RunnerNewer | Error::RunnerOlder => "Incompatible Runner",
+        Error::Unsupported(_) | Error::UnsupportedInstanceType(_) => "Unsupported by Runner",
+        Error::File(_) | Error::ChecksumMismatch => "Upload Error",
+        Error::Validation(_) => "Invalid Instance",
+        _ => "Instance Error",
+    }
+}
+
 #[tauri::command]
 pub async fn del_instance(app: AppHandle, runner: String, instance: String) -> Result<(), String> {
     let state = app.state::<AppState>();
@@ -113,7 +247,7 @@ pub async fn del_instance(app: AppHandle, runner: String, instance: String) -> R
                 Err(e) => {
                     app.dialog()
                         .message(e.to_string())
-                        .title("Instance Error")
+                        .title(error_title(&e))
                         .show(|_| {});
 
                     return Err(e.to_string());
@@ -144,7 +278,7 @@ pub async fn new_instance(app: AppHandle, runner: String, instance: InstanceRequ
                 Err(e) => {
                     app.dialog()
                         .message(e.to_string())
-                        .title("Instance Error")
+                        .title(error_title(&e))
                         .show(|_| {});
 
                     return Err(e.to_string());
@@ -164,6 +298,26 @@ pub async fn new_instance(app: AppHandle, runner: String, instance: InstanceRequ
     Ok(())
 }
 
+/// Checks an instance request without sending it. With `probe`, source
+/// URLs are requested to check whether they are reachable, using the TLS
+/// settings of the runner.
+#[tauri::command]
+pub async fn validate_instance(
+    app: AppHandle,
+    runner: String,
+    instance: InstanceRequest,
+    probe: bool,
+) -> Result<ValidationReport, String> {
+    let state = app.state::<AppState>();
+
+    let runner = state.runners.lock().await.get(&runner).cloned();
+
+    match runner {
+        Some(runner) => Ok(validate_request(&instance, probe.then_some(runner.as_ref())).await),
+        None => Err("Runner not found".to_string()),
+    }
+}
+
 #[tauri::command]
 pub async fn start_instance(app: AppHandle, runner: String, instance: String) -> Result<(), String> {
     let state = app.state::<AppState>();
@@ -175,7 +329,7 @@ pub async fn start_instance(app: AppHandle, runner: String, instance: String) ->
                 Err(e) => {
                     app.dialog()
                         .message(e.to_string())
-                        .title("Instance Error")
+                        .title(error_title(&e))
                         .show(|_| {});
 
                     return Err(e.to_string());
@@ -206,7 +360,7 @@ pub async fn stop_instance(app: AppHandle, runner: String, instance: String) ->
                 Err(e) => {
                     app.dialog()
                         .message(e.to_string())
-                        .title("Instance Error")
+                        .title(error_title(&e))
                         .show(|_| {});
 
                     return Err(e.to_string());
@@ -225,3 +379,66 @@ pub async fn stop_instance(app: AppHandle, runner: String, instance: String) ->
 
     Ok(())
 }
+
+/// Lets the user pick a file to upload, returns `None` if cancelled
+#[tauri::command]
+pub async fn pick_upload_file(app: AppHandle) -> Result<Option<String>, String> {
+    let (tx, rx) = oneshot::channel();
+
+    app.dialog()
+        .file()
+        .pick_file(move |path| {
+            let _ = tx.send(path);
+        });
+
+    match rx.await {
+        Ok(Some(path)) => match path.into_path() {
+            Ok(path) => Ok(Some(path.to_string_lossy().to_string())),
+            Err(_) => Err("Unsupported file location".to_string()),
+        },
+        _ => Ok(None),
+    }
+}
+
+/// Uploads a local file to the runner and creates a Volkanic instance
+/// from it, emitting `upload-progress` while uploading
+#[tauri::command]
+pub async fn new_instance_upload(app: AppHandle, runner: String, name: String, path: String) -> Result<(), String> {
+    let state = app.state::<AppState>();
+
+    // Not holding the lock, uploads may take a while
+    let found = state.runners.lock().await.get(&runner).cloned();
+
+    match found {
+        Some(found) => {
+            let progress = |progress: UploadProgress| {
+                let _ = app.emit("upload-progress", UiUploadProgress {
+                    runner: runner.clone(),
+                    path: path.clone(),
+                    sent: progress.sent,
+                    total: progress.total,
+                });
+            };
+
+            match found.new_instance_from_file(name, &PathBuf::from(&path), progress).await {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    app.dialog()
+                        .message(e.to_string())
+                        .title(error_title(&e))
+                        .show(|_| {});
+
+                    Err(e.to_string())
+                }
+            }
+        }
+        None => {
+            app.dialog()
+                .message("Runner not found")
+                .title("Runner Error")
+                .show(|_| {});
+
+            Err("Runner not found".to_string())
+        }
+    }
+}
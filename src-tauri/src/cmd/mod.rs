@@ -1,3 +1,6 @@
+pub mod console;
 pub mod instance;
 pub mod misc;
 pub mod runner;
+pub mod stats;
+pub mod template;
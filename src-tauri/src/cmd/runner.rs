@@ -1,53 +1,229 @@
+use futures_util::future::join_all;
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, sync::Arc};
+use std::{collections::{HashMap, HashSet}, sync::Arc};
 use tauri::{
     AppHandle,
     Emitter,
     Manager
 };
 use tauri_plugin_dialog::DialogExt;
-use tokio::sync::Mutex;
-use tracing::info;
+use tokio::sync::{broadcast::error::RecvError, Mutex};
+use tracing::{error, info, warn};
 use uuid::Uuid;
 
-use super::instance::UiInstance;
+use super::{console::forward_console, instance::UiInstance, stats::forward_stats};
 
 use crate::{
-    runner::{Runner, RunnerConDetails, RunnerMode},
+    runner::{
+        auth::RunnerAuth,
+        backoff::BackoffPolicy,
+        change::RunnerChange,
+        connection::ConnectionStatus,
+        protocol::{Capability, Compatibility},
+        health::RunnerHealth,
+        tls::TlsSettings,
+        Runner,
+        RunnerConDetails,
+        RunnerMode,
+    },
     AppState,
 };
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct UiRunner {
     pub name: String,
     pub url: String,
-    pub connected: bool,
+    pub status: UiConnectionStatus,
+    /// Smoothed heartbeat round-trip time in milliseconds
+    pub latency: Option<u64>,
+    /// Heartbeats failed in a row
+    pub failures: u32,
+    pub last_seen: Option<u64>,
+    pub health: Option<UiRunnerHealth>,
+    pub compatibility: UiCompatibility,
+    /// Protocol version negotiated with the runner
+    pub protocol: Option<u64>,
+    pub capabilities: Vec<UiCapability>,
+    pub instance_types: Vec<String>,
     pub instances: HashMap<String, UiInstance>,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Deserialize, Serialize)]
+pub enum UiCapability {
+    #[serde(rename = "instances")]
+    Instances,
+    #[serde(rename = "events")]
+    Events,
+    #[serde(rename = "console")]
+    Console,
+    #[serde(rename = "input")]
+    Input,
+    #[serde(rename = "stats")]
+    Stats,
+    #[serde(rename = "backups")]
+    Backups,
+    #[serde(rename = "files")]
+    Files,
+    #[serde(rename = "uploads")]
+    Uploads,
+}
+
+impl From<Capability> for UiCapability {
+    fn from(value: Capability) -> Self {
+        match value {
+            Capability::Instances => Self::Instances,
+            Capability::Events => Self::Events,
+            Capability::Console => Self::Console,
+            Capability::Input => Self::Input,
+            Capability::Stats => Self::Stats,
+            Capability::Backups => Self::Backups,
+            Capability::Files => Self::Files,
+            Capability::Uploads => Self::Uploads,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum UiCompatibility {
+    #[serde(rename = "unknown")]
+    Unknown,
+    #[serde(rename = "compatible")]
+    Compatible,
+    #[serde(rename = "runnerNewer")]
+    RunnerNewer,
+    #[serde(rename = "runnerOlder")]
+    RunnerOlder,
+}
+
+impl From<Compatibility> for UiCompatibility {
+    fn from(value: Compatibility) -> Self {
+        match value {
+            Compatibility::Unknown => Self::Unknown,
+            Compatibility::Compatible(_) => Self::Compatible,
+            Compatibility::RunnerNewer => Self::RunnerNewer,
+            Compatibility::RunnerOlder => Self::RunnerOlder,
+        }
+    }
+}
+
+/// Change to a single runner, emitted as the "runner-change" event
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(tag = "type", rename_all_fields = "camelCase")]
+pub enum UiRunnerChange {
+    /// Sent when a runner is added or its details or instances were
+    /// replaced as a whole
+    #[serde(rename = "runner-updated")]
+    RunnerUpdated { runner: String, details: UiRunner },
+    #[serde(rename = "runner-removed")]
+    RunnerRemoved { runner: String },
+    #[serde(rename = "status-changed")]
+    StatusChanged {
+        runner: String,
+        status: UiConnectionStatus,
+        latency: Option<u64>,
+        failures: u32,
+        last_seen: Option<u64>,
+    },
+    #[serde(rename = "health-changed")]
+    HealthChanged { runner: String, health: Option<UiRunnerHealth> },
+    #[serde(rename = "instance-upserted")]
+    InstanceUpserted { runner: String, id: String, instance: UiInstance },
+    #[serde(rename = "instance-removed")]
+    InstanceRemoved { runner: String, id: String },
+}
+
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum UiConnectionStatus {
+    #[serde(rename = "online")]
+    Online,
+    #[serde(rename = "degraded")]
+    Degraded,
+    #[serde(rename = "offline")]
+    Offline,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiRunnerHealth {
+    pub version: String,
+    pub uptime: Option<u64>,
+    /// Load averages over 1, 5 and 15 minutes
+    pub load: Vec<f64>,
+    pub cpus: Option<u64>,
+    pub memory_used: Option<u64>,
+    pub memory_total: Option<u64>,
+    pub disk_used: Option<u64>,
+    pub disk_total: Option<u64>,
+    pub instances_total: Option<u64>,
+    pub instances_running: Option<u64>,
+}
+
+impl From<ConnectionStatus> for UiConnectionStatus {
+    fn from(value: ConnectionStatus) -> Self {
+        match value {
+            ConnectionStatus::Online => Self::Online,
+            ConnectionStatus::Degraded => Self::Degraded,
+            ConnectionStatus::Offline => Self::Offline,
+        }
+    }
+}
+
+impl From<RunnerHealth> for UiRunnerHealth {
+    fn from(value: RunnerHealth) -> Self {
+        let host = value.host.unwrap_or_default();
+
+        Self {
+            version: value.version,
+            uptime: value.uptime,
+            load: host.load,
+            cpus: host.cpus,
+            memory_used: host.memory_used,
+            memory_total: host.memory_total,
+            disk_used: host.disk_used,
+            disk_total: host.disk_total,
+            instances_total: value.instances.as_ref().map(|i| i.total),
+            instances_running: value.instances.as_ref().map(|i| i.running),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
 pub struct RunnerInfoInterface {
     pub version: String,
     pub protocol: u64,
     pub mode: RunnerModeInterface,
+    pub compatibility: UiCompatibility,
+    pub capabilities: Vec<UiCapability>,
+    pub instance_types: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum RunnerModeInterface {
     #[serde(rename = "noAuth")]
     NoAuth,
+    #[serde(rename = "token")]
+    Token,
+    #[serde(rename = "login")]
+    Login,
 }
 
 #[tauri::command]
-pub async fn runner_info(url: String) -> Result<RunnerInfoInterface, String> {
-    let info = Runner::info(url).await.map_err(|e| e.to_string())?;
+pub async fn runner_info(url: String, tls: Option<TlsSettings>) -> Result<RunnerInfoInterface, String> {
+    let info = Runner::info(url, &tls.unwrap_or_default()).await.map_err(|e| e.to_string())?;
 
     Ok(RunnerInfoInterface {
         version: info.version,
         protocol: info.protocol,
         mode: match info.mode {
             RunnerMode::NoAuth => RunnerModeInterface::NoAuth,
+            RunnerMode::Token => RunnerModeInterface::Token,
+            RunnerMode::Login => RunnerModeInterface::Login,
         },
+        compatibility: info.compatibility.into(),
+        capabilities: to_ui_capabilities(info.capabilities),
+        instance_types: to_sorted(info.instance_types),
     })
 }
 
@@ -59,10 +235,85 @@ pub async fn runner_list(app: AppHandle) -> HashMap<String, UiRunner> {
 }
 
 #[tauri::command]
-pub async fn runner_new(app: AppHandle, name: String, url: String) -> Result<(), String> {
+pub async fn runner_new(
+    app: AppHandle,
+    name: String,
+    url: String,
+    auth: Option<RunnerAuth>,
+    tls: Option<TlsSettings>,
+    backoff: Option<BackoffPolicy>,
+) -> Result<(), String> {
+    let app = Arc::new(app);
+
+    match m_runner_new(
+        app.clone(),
+        name,
+        url,
+        auth.unwrap_or_default(),
+        tls.unwrap_or_default(),
+        backoff.unwrap_or_default(),
+    ).await {
+        Ok(_) => {Ok(())},
+        Err(e) => {
+            app.dialog()
+                .message(&e)
+                .title("Runner Error")
+                .show(|_| {});
+
+            Err(e)
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn runner_remove(app: AppHandle, id: String) -> Result<(), String> {
     let app = Arc::new(app);
 
-    match m_runner_new(app.clone(), name, url).await {
+    match m_runner_remove(app.clone(), id).await {
+        Ok(_) => {Ok(())},
+        Err(e) => {
+            app.dialog()
+                .message(&e)
+                .title("Runner Error")
+                .show(|_| {});
+
+            Err(e)
+        }
+    }
+}
+
+/// Skips the pending reconnection delays of a runner and retries right away
+#[tauri::command]
+pub async fn runner_reconnect(app: AppHandle, id: String) -> Result<(), String> {
+    let state = app.state::<AppState>();
+
+    let runner = state.runners.lock().await.get(&id).cloned();
+
+    match runner {
+        Some(runner) => {
+            runner.reconnect();
+
+            Ok(())
+        }
+        None => Err(String::from("Runner not found")),
+    }
+}
+
+/// Credentials, TLS settings and the backoff policy are left unchanged if
+/// not provided
+#[tauri::command]
+pub async fn runner_update(
+    app: AppHandle,
+    id: String,
+    name: String,
+    url: String,
+    auth: Option<RunnerAuth>,
+    tls: Option<TlsSettings>,
+    backoff: Option<BackoffPolicy>,
+) -> Result<(), String> {
+    let app = Arc::new(app);
+
+    match m_runner_update(app.clone(), id, name, url, auth, tls, backoff).await {
         Ok(_) => {Ok(())},
         Err(e) => {
             app.dialog()
@@ -81,54 +332,310 @@ pub async fn send_runners(app: Arc<AppHandle>) -> Result<(), String> {
     app.emit("runner", to_ui_runners(state.runners.clone()).await).map_err(|e| e.to_string())
 }
 
-async fn m_runner_new(app: Arc<AppHandle>, name: String, url: String) -> Result<(), String> {
+/// Reconnects every runner saved in the config file
+pub async fn restore_runners(app: Arc<AppHandle>) {
+    let state = app.state::<AppState>();
+
+    let saved = state.config.lock().await.config.runners.clone();
+
+    for (id, details) in saved {
+        let name = details.name.clone();
+
+        let runner = Runner::restore(details).await;
+
+        state.runners.lock().await.insert(id.clone(), runner.clone());
+
+        info!("Restored runner (\"{}\")", name);
+
+        forward_updates(app.clone(), id.clone(), runner.clone()).await;
+        forward_console(app.clone(), id.clone(), runner.clone()).await;
+        forward_stats(app.clone(), id, runner).await;
+    }
+
+    let _ = send_runners(app).await;
+}
+
+/// Stops the background tasks of all runners, e.g. before the app exits
+pub async fn shutdown_runners(app: &AppHandle) {
+    let state = app.state::<AppState>();
+
+    let runners: Vec<_> = state.runners.lock().await.values().cloned().collect();
+
+    join_all(runners.iter().map(|r| r.shutdown())).await;
+
+    info!("Shut down {} runner(s)", runners.len());
+}
+
+async fn m_runner_new(
+    app: Arc<AppHandle>,
+    name: String,
+    url: String,
+    auth: RunnerAuth,
+    tls: TlsSettings,
+    backoff: BackoffPolicy,
+) -> Result<(), String> {
     let state = app.state::<AppState>();
 
     let details = RunnerConDetails {
         name: name.clone(),
         url: url.trim_end_matches('/').to_string(),
+        auth,
+        secret_id: None,
+        tls,
+        backoff,
     };
 
-    let runner = Runner::new(details).await.map_err(|e| e.to_string())?;
+    let runner = Runner::new(details.clone()).await.map_err(|e| e.to_string())?;
     let uuid = Uuid::new_v4().to_string();
 
     if state.runners.lock().await.contains_key(&uuid) {
+        runner.shutdown().await;
         return Err(String::from("Runner ID collision error"));
     };
 
-    state.runners.lock().await.insert(uuid, runner.clone());
+    {
+        let mut config = state.config.lock().await;
 
-    info!("Added runner (\"{}\")", name);
+        config.config.runners.insert(uuid.clone(), details);
 
-    tokio::spawn(async move {
-        loop {
-            let _ = send_runners(app.clone()).await;
+        if let Err(e) = config.update().await {
+            error!("Failed to save runner to config: {}", e);
+
+            // Otherwise the next successful save would write it anyway
+            config.config.runners.remove(&uuid);
+            drop(config);
+            runner.shutdown().await;
 
-            runner.wait_for_update().await.unwrap();
+            return Err(format!("Failed to save runner: {}", e));
         }
+    }
+
+    state.runners.lock().await.insert(uuid.clone(), runner.clone());
+
+    info!("Added runner (\"{}\")", name);
+
+    send_change(&app, UiRunnerChange::RunnerUpdated {
+        runner: uuid.clone(),
+        details: to_ui_runner(&runner).await,
     });
 
+    forward_updates(app.clone(), uuid.clone(), runner.clone()).await;
+    forward_console(app.clone(), uuid.clone(), runner.clone()).await;
+    forward_stats(app, uuid, runner).await;
+
     Ok(())
 }
 
-async fn to_ui_runners(runners: Arc<Mutex<HashMap<String, Arc<Runner>>>>) -> HashMap<String, UiRunner> {
-    let mut ui_runners = HashMap::new();
+async fn m_runner_remove(app: Arc<AppHandle>, id: String) -> Result<(), String> {
+    let state = app.state::<AppState>();
 
-    for r in runners.lock().await.iter() {
-        let mut instances = HashMap::new();
+    let runner = match state.runners.lock().await.get(&id) {
+        Some(o) => o.clone(),
+        None => return Err(String::from("Runner not found")),
+    };
+
+    // Saved first so the runner keeps running if that fails
+    {
+        let mut config = state.config.lock().await;
 
-        for i in r.1.get_instances().await {
-            instances.insert(i.0, i.1.into());
+        let previous = config.config.runners.remove(&id);
+
+        if let Err(e) = config.update().await {
+            error!("Failed to remove runner from config: {}", e);
+
+            if let Some(previous) = previous {
+                config.config.runners.insert(id, previous);
+            }
+
+            return Err(format!("Failed to save runners: {}", e));
         }
+    }
+
+    state.runners.lock().await.remove(&id);
+
+    runner.shutdown().await;
+
+    info!("Removed runner (\"{}\")", runner.get_name().await);
+
+    send_change(&app, UiRunnerChange::RunnerRemoved { runner: id });
+
+    Ok(())
+}
+
+async fn m_runner_update(
+    app: Arc<AppHandle>,
+    id: String,
+    name: String,
+    url: String,
+    auth: Option<RunnerAuth>,
+    tls: Option<TlsSettings>,
+    backoff: Option<BackoffPolicy>,
+) -> Result<(), String> {
+    let state = app.state::<AppState>();
+
+    let runner = match state.runners.lock().await.get(&id) {
+        Some(o) => o.clone(),
+        None => return Err(String::from("Runner not found")),
+    };
+
+    let old_details = runner.get_details().await;
+
+    let details = RunnerConDetails {
+        name: name.clone(),
+        url: url.trim_end_matches('/').to_string(),
+        auth: auth.unwrap_or(old_details.auth.clone()),
+        secret_id: old_details.secret_id.clone(),
+        tls: tls.unwrap_or(old_details.tls.clone()),
+        backoff: backoff.unwrap_or(old_details.backoff.clone()),
+    };
+
+    let tls_changed = details.tls != old_details.tls;
+
+    if details.url != old_details.url || tls_changed {
+        // Make sure the runner is still reachable and compatible before switching
+        Runner::info(&details.url, &details.tls).await.map_err(|e| e.to_string())?;
+    }
+
+    if details.url != old_details.url || details.auth != old_details.auth || tls_changed {
+        Runner::check_auth(&details).await.map_err(|e| e.to_string())?;
+    }
 
-        let ui_r = UiRunner {
-            name: r.1.get_name().await,
-            url: r.1.get_url().await,
-            connected: r.1.is_connected().await,
-            instances,
-        };
+    {
+        let mut config = state.config.lock().await;
 
-        ui_runners.insert(r.0.to_string(), ui_r.clone());
+        let previous = config.config.runners.insert(id.clone(), details.clone());
+
+        if let Err(e) = config.update().await {
+            error!("Failed to save runner to config: {}", e);
+
+            match previous {
+                Some(previous) => config.config.runners.insert(id, previous),
+                None => config.config.runners.remove(&id),
+            };
+
+            return Err(format!("Failed to save runner: {}", e));
+        }
+    }
+
+    runner.set_details(details).await;
+
+    info!("Updated runner (\"{}\")", name);
+
+    Ok(())
+}
+
+fn send_change(app: &AppHandle, change: UiRunnerChange) {
+    if let Err(e) = app.emit("runner-change", change) {
+        error!("Failed to send runner change: {}", e);
+    }
+}
+
+/// Sends changes of the runner to the frontend. If changes were missed,
+/// the full runner list is sent instead.
+async fn forward_updates(app: Arc<AppHandle>, id: String, runner: Arc<Runner>) {
+    let mut rx = runner.subscribe_changes();
+    let forward_runner = runner.clone();
+
+    let task = runner.spawn_cancellable(async move {
+        loop {
+            let change = match rx.recv().await {
+                Ok(o) => o,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("Missed {} runner changes, resyncing", n);
+                    let _ = send_runners(app.clone()).await;
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let change = match change {
+                RunnerChange::Details | RunnerChange::Instances => UiRunnerChange::RunnerUpdated {
+                    runner: id.clone(),
+                    details: to_ui_runner(&forward_runner).await,
+                },
+                RunnerChange::Connection => {
+                    let connection = forward_runner.get_connection().await;
+
+                    UiRunnerChange::StatusChanged {
+                        runner: id.clone(),
+                        status: connection.status.into(),
+                        latency: connection.latency_ms,
+                        failures: connection.failures,
+                        last_seen: connection.last_seen,
+                    }
+                }
+                RunnerChange::Health => UiRunnerChange::HealthChanged {
+                    runner: id.clone(),
+                    health: forward_runner.get_health().await.map(|h| h.into()),
+                },
+                RunnerChange::InstanceUpserted { id: instance_id, instance } => {
+                    UiRunnerChange::InstanceUpserted {
+                        runner: id.clone(),
+                        id: instance_id,
+                        instance: (*instance).into(),
+                    }
+                }
+                RunnerChange::InstanceRemoved { id: instance_id } => UiRunnerChange::InstanceRemoved {
+                    runner: id.clone(),
+                    id: instance_id,
+                },
+            };
+
+            send_change(&app, change);
+        }
+    });
+
+    runner.attach_task(task).await;
+}
+
+async fn to_ui_runner(runner: &Runner) -> UiRunner {
+    let mut instances = HashMap::new();
+
+    for i in runner.get_instances().await {
+        instances.insert(i.0, i.1.into());
+    }
+
+    let connection = runner.get_connection().await;
+    let protocol = runner.get_protocol().await;
+
+    UiRunner {
+        name: runner.get_name().await,
+        url: runner.get_url().await,
+        status: connection.status.into(),
+        latency: connection.latency_ms,
+        failures: connection.failures,
+        last_seen: connection.last_seen,
+        health: runner.get_health().await.map(|h| h.into()),
+        compatibility: protocol.compatibility.into(),
+        protocol: match protocol.compatibility {
+            Compatibility::Compatible(version) => Some(version),
+            _ => None,
+        },
+        capabilities: to_ui_capabilities(protocol.capabilities),
+        instance_types: to_sorted(protocol.instance_types),
+        instances,
+    }
+}
+
+fn to_ui_capabilities(capabilities: HashSet<Capability>) -> Vec<UiCapability> {
+    let mut capabilities: Vec<UiCapability> = capabilities.into_iter().map(|c| c.into()).collect();
+    capabilities.sort();
+
+    capabilities
+}
+
+fn to_sorted(set: HashSet<String>) -> Vec<String> {
+    let mut list: Vec<String> = set.into_iter().collect();
+    list.sort();
+
+    list
+}
+
+async fn to_ui_runners(runners: Arc<Mutex<HashMap<String, Arc<Runner>>>>) -> HashMap<String, UiRunner> {
+    let mut ui_runners = HashMap::new();
+
+    for r in runners.lock().await.iter() {
+        ui_runners.insert(r.0.to_string(), to_ui_runner(r.1).await);
     }
 
     ui_runners
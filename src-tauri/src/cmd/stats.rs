@@ -0,0 +1,137 @@
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use tauri::{AppHandle, Emitter, Manager};
+use tauri_plugin_dialog::DialogExt;
+use tokio::sync::broadcast::error::RecvError;
+use tracing::warn;
+
+use super::instance::error_title;
+
+use crate::{
+    runner::{stats::StatsSample, Runner},
+    AppState,
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiStatsSample {
+    time: u64,
+    cpu: f64,
+    memory: u64,
+    memory_limit: Option<u64>,
+    uptime: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiStatsEvent {
+    runner: String,
+    instance: String,
+    sample: UiStatsSample,
+}
+
+impl From<StatsSample> for UiStatsSample {
+    fn from(value: StatsSample) -> Self {
+        Self {
+            time: value.time,
+            cpu: value.stats.cpu,
+            memory: value.stats.memory,
+            memory_limit: value.stats.memory_limit,
+            uptime: value.stats.uptime,
+        }
+    }
+}
+
+/// Returns the recorded resource usage of an instance, oldest first
+#[tauri::command]
+pub async fn instance_stats(app: AppHandle, runner: String, instance: String) -> Result<Vec<UiStatsSample>, String> {
+    let state = app.state::<AppState>();
+
+    let runner = state.runners.lock().await.get(&runner).cloned();
+
+    match runner {
+        Some(runner) => {
+            match runner.get_stats(&instance).await {
+                Ok(samples) => Ok(samples.into_iter().map(|s| s.into()).collect()),
+                Err(e) => {
+                    app.dialog()
+                        .message(e.to_string())
+                        .title(error_title(&e))
+                        .show(|_| {});
+
+                    Err(e.to_string())
+                }
+            }
+        }
+        None => Err("Runner not found".to_string()),
+    }
+}
+
+/// Starts pulling the resource usage of an instance on an interval,
+/// returning the recorded samples
+#[tauri::command]
+pub async fn stats_watch(app: AppHandle, runner: String, instance: String) -> Result<Vec<UiStatsSample>, String> {
+    let state = app.state::<AppState>();
+
+    let runner = state.runners.lock().await.get(&runner).cloned();
+
+    match runner {
+        Some(runner) => {
+            match runner.watch_stats(&instance).await {
+                Ok(samples) => Ok(samples.into_iter().map(|s| s.into()).collect()),
+                Err(e) => {
+                    app.dialog()
+                        .message(e.to_string())
+                        .title(error_title(&e))
+                        .show(|_| {});
+
+                    Err(e.to_string())
+                }
+            }
+        }
+        None => Err("Runner not found".to_string()),
+    }
+}
+
+/// Stops pulling the resource usage of an instance
+#[tauri::command]
+pub async fn stats_unwatch(app: AppHandle, runner: String, instance: String) -> Result<(), String> {
+    let state = app.state::<AppState>();
+
+    let runner = state.runners.lock().await.get(&runner).cloned();
+
+    match runner {
+        Some(runner) => {
+            runner.unwatch_stats(&instance).await;
+
+            Ok(())
+        }
+        None => Err("Runner not found".to_string()),
+    }
+}
+
+/// Emits resource usage of the runner's instances to the frontend
+pub async fn forward_stats(app: Arc<AppHandle>, id: String, runner: Arc<Runner>) {
+    let mut rx = runner.subscribe_stats();
+
+    let task = runner.spawn_cancellable(async move {
+        loop {
+            let (instance, sample) = match rx.recv().await {
+                Ok(o) => o,
+                Err(RecvError::Lagged(n)) => {
+                    warn!("Dropped {} stats samples", n);
+                    continue;
+                }
+                Err(RecvError::Closed) => break,
+            };
+
+            let _ = app.emit("stats", UiStatsEvent {
+                runner: id.clone(),
+                instance,
+                sample: sample.into(),
+            });
+        }
+    });
+
+    runner.attach_task(task).await;
+}
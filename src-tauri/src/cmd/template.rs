@@ -0,0 +1,362 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+use tauri::{AppHandle, Manager};
+use tauri_plugin_dialog::{DialogExt, FilePath};
+use tokio::{fs, sync::oneshot};
+use tracing::{error, info};
+use uuid::Uuid;
+
+use super::instance::{error_title, UiInstanceType};
+
+use crate::{
+    runner::{instance::InstanceType, validate::validate_request},
+    template::{default_name_pattern, InstanceTemplate},
+    AppState,
+};
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiTemplate {
+    id: String,
+    name: String,
+    #[serde(rename = "type")]
+    inst_type: UiInstanceType,
+    name_pattern: String,
+}
+
+/// Template as sent by the frontend, also the format of export files
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UiTemplateRequest {
+    /// Not set for templates that weren't saved yet, never exported
+    #[serde(default, skip_serializing)]
+    id: Option<String>,
+    name: String,
+    /// Kept as JSON so exports keep types unknown to this console
+    #[serde(rename = "type")]
+    inst_type: Value,
+    #[serde(default, alias = "name_pattern")]
+    name_pattern: Option<String>,
+}
+
+impl UiTemplate {
+    fn new(id: String, template: InstanceTemplate) -> Self {
+        Self {
+            id,
+            name: template.name,
+            inst_type: InstanceType::decode(&template.inst_type).into(),
+            name_pattern: template.name_pattern,
+        }
+    }
+}
+
+impl From<UiTemplateRequest> for InstanceTemplate {
+    fn from(value: UiTemplateRequest) -> Self {
+        Self {
+            name: value.name,
+            inst_type: value.inst_type,
+            name_pattern: match value.name_pattern {
+                Some(pattern) if !pattern.trim().is_empty() => pattern,
+                _ => default_name_pattern(),
+            },
+        }
+    }
+}
+
+impl From<InstanceTemplate> for UiTemplateRequest {
+    fn from(value: InstanceTemplate) -> Self {
+        Self {
+            id: None,
+            name: value.name,
+            inst_type: value.inst_type,
+            name_pattern: Some(value.name_pattern),
+        }
+    }
+}
+
+#[tauri::command]
+pub async fn template_list(app: AppHandle) -> Vec<UiTemplate> {
+    let state = app.state::<AppState>();
+
+    let mut templates: Vec<UiTemplate> = state
+        .config
+        .lock()
+        .await
+        .config
+        .templates
+        .iter()
+        .map(|(id, template)| UiTemplate::new(id.clone(), template.clone()))
+        .collect();
+
+    templates.sort_by(|a, b| a.name.cmp(&b.name));
+
+    templates
+}
+
+/// Saves a template, replacing the existing one if it has an ID.
+/// Returns the ID of the template.
+#[tauri::command]
+pub async fn template_save(app: AppHandle, template: UiTemplateRequest) -> Result<String, String> {
+    let state = app.state::<AppState>();
+
+    let id = template.id.clone().unwrap_or_else(|| Uuid::new_v4().to_string());
+    let template: InstanceTemplate = template.into();
+
+    check_template(&template).await?;
+
+    let mut config = state.config.lock().await;
+
+    let previous = config.config.templates.insert(id.clone(), template);
+
+    if let Err(e) = config.update().await {
+        error!("Failed to save template to config: {}", e);
+
+        match previous {
+            Some(previous) => config.config.templates.insert(id, previous),
+            None => config.config.templates.remove(&id),
+        };
+
+        return Err(format!("Failed to save template: {}", e));
+    }
+
+    Ok(id)
+}
+
+#[tauri::command]
+pub async fn template_delete(app: AppHandle, id: String) -> Result<(), String> {
+    let state = app.state::<AppState>();
+
+    let mut config = state.config.lock().await;
+
+    let template = match config.config.templates.remove(&id) {
+        Some(o) => o,
+        None => return Err("Template not found".to_string()),
+    };
+
+    if let Err(e) = config.update().await {
+        error!("Failed to remove template from config: {}", e);
+
+        config.config.templates.insert(id, template);
+
+        return Err(format!("Failed to remove template: {}", e));
+    }
+
+    Ok(())
+}
+
+/// Creates an instance from a template. Without a name, the name is
+/// generated from the template's name pattern.
+#[tauri::command]
+pub async fn instance_from_template(
+    app: AppHandle,
+    runner: String,
+    template: String,
+    name: Option<String>,
+) -> Result<(), String> {
+    let state = app.state::<AppState>();
+
+    let template = match state.config.lock().await.config.templates.get(&template) {
+        Some(o) => o.clone(),
+        None => return Err("Template not found".to_string()),
+    };
+
+    let runner = state.runners.lock().await.get(&runner).cloned();
+
+    match runner {
+        Some(runner) => {
+            let name = match name {
+                Some(name) => name,
+                None => {
+                    let taken: HashSet<String> = runner
+                        .get_instances()
+                        .await
+                        .into_values()
+                        .map(|i| i.name)
+                        .collect();
+
+                    template.instance_name(&taken)
+                }
+            };
+
+            let request = match template.to_request(name) {
+                Ok(o) => o,
+                Err(e) => {
+                    let message = format!("The template is invalid: {}", e);
+
+                    app.dialog()
+                        .message(&message)
+                        .title("Template Error")
+                        .show(|_| {});
+
+                    return Err(message);
+                }
+            };
+
+            match runner.new_instance(request).await {
+                Ok(_) => Ok(()),
+                Err(e) => {
+                    app.dialog()
+                        .message(e.to_string())
+                        .title(error_title(&e))
+                        .show(|_| {});
+
+                    Err(e.to_string())
+                }
+            }
+        }
+        None => {
+            app.dialog()
+                .message("Runner not found")
+                .title("Runner Error")
+                .show(|_| {});
+
+            Err("Runner not found".to_string())
+        }
+    }
+}
+
+/// Writes templates to a JSON file chosen by the user, all templates if
+/// no IDs are given. Returns false if cancelled.
+#[tauri::command]
+pub async fn template_export(app: AppHandle, ids: Option<Vec<String>>) -> Result<bool, String> {
+    let state = app.state::<AppState>();
+
+    let templates: Vec<UiTemplateRequest> = {
+        let config = state.config.lock().await;
+
+        match ids {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| config.config.templates.get(id).cloned())
+                .map(UiTemplateRequest::from)
+                .collect(),
+            None => config.config.templates.values().cloned().map(UiTemplateRequest::from).collect(),
+        }
+    };
+
+    let (tx, rx) = oneshot::channel();
+
+    app.dialog()
+        .file()
+        .add_filter("JSON", &["json"])
+        .set_file_name("templates.json")
+        .save_file(move |path| {
+            let _ = tx.send(path);
+        });
+
+    let path = match rx.await {
+        Ok(Some(path)) => to_path(path)?,
+        _ => return Ok(false),
+    };
+
+    let mut raw = serde_jsonc::to_string_pretty(&templates).map_err(|e| e.to_string())?;
+    raw.push('\n');
+
+    if let Err(e) = fs::write(&path, raw).await {
+        error!("Failed to export templates: {}", e);
+        return Err(format!("Failed to export templates: {}", e));
+    }
+
+    info!("Exported {} templates", templates.len());
+
+    Ok(true)
+}
+
+/// Adds templates from a JSON file chosen by the user, returns the
+/// imported templates
+#[tauri::command]
+pub async fn template_import(app: AppHandle) -> Result<Vec<UiTemplate>, String> {
+    let state = app.state::<AppState>();
+
+    let (tx, rx) = oneshot::channel();
+
+    app.dialog()
+        .file()
+        .add_filter("JSON", &["json"])
+        .pick_file(move |path| {
+            let _ = tx.send(path);
+        });
+
+    let path = match rx.await {
+        Ok(Some(path)) => to_path(path)?,
+        _ => return Ok(Vec::new()),
+    };
+
+    let raw = fs::read_to_string(&path).await.map_err(|e| format!("Failed to read templates: {}", e))?;
+
+    let templates: Vec<InstanceTemplate> = match serde_jsonc::from_str::<Vec<UiTemplateRequest>>(&raw) {
+        Ok(o) => o.into_iter().map(InstanceTemplate::from).collect(),
+        Err(e) => {
+            app.dialog()
+                .message(format!("The file doesn't contain valid templates: {}", e))
+                .title("Import Error")
+                .show(|_| {});
+
+            return Err(e.to_string());
+        }
+    };
+
+    // Nothing is imported if any template is invalid. Types are only
+    // checked once instances are created, so templates of types this
+    // console doesn't know can still be passed on.
+    for template in &templates {
+        if let Err(e) = template.check_structure() {
+            let message = format!("Template \"{}\" is invalid: {}", template.name, e);
+
+            app.dialog()
+                .message(&message)
+                .title("Import Error")
+                .show(|_| {});
+
+            return Err(message);
+        }
+    }
+
+    let imported: Vec<(String, InstanceTemplate)> = templates
+        .into_iter()
+        .map(|template| (Uuid::new_v4().to_string(), template))
+        .collect();
+
+    {
+        let mut config = state.config.lock().await;
+
+        config.config.templates.extend(imported.iter().cloned());
+
+        if let Err(e) = config.update().await {
+            error!("Failed to save imported templates to config: {}", e);
+
+            for (id, _) in &imported {
+                config.config.templates.remove(id);
+            }
+
+            return Err(format!("Failed to save templates: {}", e));
+        }
+    }
+
+    info!("Imported {} templates", imported.len());
+
+    Ok(imported
+        .into_iter()
+        .map(|(id, template)| UiTemplate::new(id, template))
+        .collect())
+}
+
+/// Fails if instances created from the template would be rejected
+async fn check_template(template: &InstanceTemplate) -> Result<(), String> {
+    template.check_structure()?;
+
+    let request = template
+        .to_request(template.instance_name(&HashSet::new()))
+        .map_err(|e| format!("The instance type is invalid: {}", e))?;
+
+    validate_request(&request, None)
+        .await
+        .into_result()
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+fn to_path(path: FilePath) -> Result<std::path::PathBuf, String> {
+    path.into_path().map_err(|_| "Unsupported file location".to_string())
+}
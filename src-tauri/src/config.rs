@@ -1,8 +1,13 @@
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, path::PathBuf};
-use tokio::{fs, io::AsyncWriteExt};
+use std::{collections::HashMap, path::PathBuf, sync::Arc};
+use tokio::{fs, io::AsyncWriteExt, task};
+use tracing::{info, warn};
 
-use crate::runner::RunnerConDetails;
+use crate::{
+    runner::{auth::RunnerAuth, RunnerConDetails},
+    secret::{self, SecretStore},
+    template::InstanceTemplate,
+};
 
 const CONF_FILE_NAME: &str = "config.json";
 const DIR_NAME: &str = "Volkanic Console";
@@ -21,16 +26,26 @@ pub enum Error {
     JsonEncode(serde_jsonc::Error),
     #[error("Cannot determine config directory")]
     NoConfigDir,
+    #[error("Secret store error: {0}")]
+    Secret(secret::Error),
+    #[error("Secret store task failed: {0}")]
+    SecretTask(task::JoinError),
 }
 
 #[derive(Debug, Clone, Default, Deserialize, Serialize)]
 pub struct Config {
     pub runners: HashMap<String, RunnerConDetails>,
+    /// Instance templates by ID
+    #[serde(default)]
+    pub templates: HashMap<String, InstanceTemplate>,
 }
 
 pub struct ConfigFile {
     pub config: Config,
     path: PathBuf,
+    secrets: Arc<dyn SecretStore>,
+    /// Secrets currently saved in the secret store, by secret ID
+    stored_secrets: HashMap<String, String>,
 }
 
 impl ConfigFile {
@@ -46,6 +61,15 @@ impl ConfigFile {
             fs::create_dir_all(&dir).await.map_err(Error::Io)?;
         }
 
+        let secrets = {
+            let dir = dir.clone();
+            blocking(move || secret::open(&dir)).await?
+        };
+
+        Self::open(dir, Arc::from(secrets)).await
+    }
+    /// Loads the config file in `dir`, creating it if it doesn't exist
+    async fn open(dir: PathBuf, secrets: Arc<dyn SecretStore>) -> Result<Self, Error> {
         let path = dir.join(CONF_FILE_NAME);
 
         if path.is_file() {
@@ -53,13 +77,36 @@ impl ConfigFile {
 
             let config = serde_jsonc::from_str(&config_raw).map_err(Error::JsonDecode)?;
 
-            Ok(ConfigFile { path, config })
+            let mut config_file = ConfigFile {
+                path,
+                config,
+                secrets,
+                stored_secrets: HashMap::new(),
+            };
+
+            // Older versions wrote credentials into the config file
+            let legacy = config_file
+                .config
+                .runners
+                .values()
+                .any(|details| details.auth != RunnerAuth::None);
+
+            config_file.load_secrets().await?;
+
+            if legacy {
+                info!("Moving runner credentials from the config file to the secret store");
+                config_file.update().await?;
+            }
+
+            Ok(config_file)
         } else if path.is_dir() {
             Err(Error::FoundDirectory(path))
         } else {
-            let config_file = Self {
+            let mut config_file = Self {
                 path,
                 config: Config::default(),
+                secrets,
+                stored_secrets: HashMap::new(),
             };
 
             config_file.update().await?;
@@ -67,7 +114,9 @@ impl ConfigFile {
             Ok(config_file)
         }
     }
-    pub async fn update(&self) -> Result<(), Error> {
+    pub async fn update(&mut self) -> Result<(), Error> {
+        self.save_secrets().await?;
+
         let mut f = fs::File::create(&self.path).await.map_err(Error::Io)?;
 
         let mut config_raw =
@@ -77,7 +126,226 @@ impl ConfigFile {
         f.write_all(config_raw.as_bytes())
             .await
             .map_err(Error::Io)?;
+        // Otherwise the write may still be pending once this returns
+        f.flush().await.map_err(Error::Io)?;
+
+        Ok(())
+    }
+    /// Fills in runner credentials from the secret store
+    async fn load_secrets(&mut self) -> Result<(), Error> {
+        let secret_ids: Vec<String> = self
+            .config
+            .runners
+            .values()
+            .filter_map(|details| details.secret_id.clone())
+            .collect();
+
+        let store = self.secrets.clone();
+        let secrets = blocking(move || {
+            secret_ids
+                .into_iter()
+                .map(|id| store.get(&id).map(|secret| (id, secret)))
+                .collect::<Result<HashMap<_, _>, _>>()
+        })
+        .await?;
+
+        for (id, details) in self.config.runners.iter_mut() {
+            let secret_id = match &details.secret_id {
+                Some(o) => o,
+                None => continue,
+            };
+
+            let secret = match secrets.get(secret_id).cloned().flatten() {
+                Some(o) => o,
+                None => {
+                    warn!(
+                        "Credentials for runner {} are missing from the secret store",
+                        id
+                    );
+                    continue;
+                }
+            };
+
+            match serde_jsonc::from_str::<RunnerAuth>(&secret) {
+                Ok(auth) => details.auth = auth,
+                Err(e) => {
+                    warn!("Credentials for runner {} are invalid: {}", id, e);
+                    continue;
+                }
+            }
+
+            self.stored_secrets.insert(secret_id.clone(), secret);
+        }
+
+        Ok(())
+    }
+    /// Moves runner credentials into the secret store, only leaving a
+    /// reference to them in the config
+    async fn save_secrets(&mut self) -> Result<(), Error> {
+        let mut stored = HashMap::new();
+        let mut changed = Vec::new();
+
+        for (id, details) in self.config.runners.iter_mut() {
+            if details.auth == RunnerAuth::None {
+                details.secret_id = None;
+                continue;
+            }
+
+            let secret_id = details
+                .secret_id
+                .get_or_insert_with(|| format!("runner-{}", id))
+                .clone();
+            let secret = serde_jsonc::to_string(&details.auth).map_err(Error::JsonEncode)?;
+
+            if self.stored_secrets.get(&secret_id) != Some(&secret) {
+                changed.push((secret_id.clone(), secret.clone()));
+            }
+
+            stored.insert(secret_id, secret);
+        }
+
+        // Credentials of deleted runners
+        let deleted: Vec<String> = self
+            .stored_secrets
+            .keys()
+            .filter(|secret_id| !stored.contains_key(*secret_id))
+            .cloned()
+            .collect();
+
+        if !changed.is_empty() || !deleted.is_empty() {
+            let store = self.secrets.clone();
+
+            blocking(move || {
+                for (secret_id, secret) in &changed {
+                    store.set(secret_id, secret)?;
+                }
+
+                for secret_id in &deleted {
+                    store.delete(secret_id)?;
+                }
+
+                Ok(())
+            })
+            .await?;
+        }
+
+        self.stored_secrets = stored;
 
         Ok(())
     }
 }
+
+/// Runs secret store operations off the async runtime, since the platform
+/// keyring blocks on D-Bus or OS calls
+async fn blocking<T, F>(f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, secret::Error> + Send + 'static,
+{
+    task::spawn_blocking(f)
+        .await
+        .map_err(Error::SecretTask)?
+        .map_err(Error::Secret)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::secret::MemoryStore;
+    use uuid::Uuid;
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("volkanic-console-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+
+        dir
+    }
+
+    fn runner(auth: RunnerAuth) -> RunnerConDetails {
+        RunnerConDetails {
+            name: String::from("Runner"),
+            url: String::from("http://localhost:8080"),
+            auth,
+            secret_id: None,
+            tls: Default::default(),
+            backoff: Default::default(),
+        }
+    }
+
+    fn token(token: &str) -> RunnerAuth {
+        RunnerAuth::Token {
+            token: token.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn credentials_stay_out_of_config_file() {
+        let dir = temp_dir();
+        let store = Arc::new(MemoryStore::default());
+
+        let mut config = ConfigFile::open(dir.clone(), store.clone()).await.unwrap();
+        config
+            .config
+            .runners
+            .insert(String::from("a"), runner(token("hunter2")));
+        config.update().await.unwrap();
+
+        let raw = std::fs::read_to_string(dir.join(CONF_FILE_NAME)).unwrap();
+        assert!(!raw.contains("hunter2"));
+        assert!(store.get("runner-a").unwrap().is_some());
+
+        let config = ConfigFile::open(dir.clone(), store).await.unwrap();
+        assert_eq!(config.config.runners["a"].auth, token("hunter2"));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn credentials_of_removed_runners_are_deleted() {
+        let dir = temp_dir();
+        let store = Arc::new(MemoryStore::default());
+
+        let mut config = ConfigFile::open(dir.clone(), store.clone()).await.unwrap();
+        config
+            .config
+            .runners
+            .insert(String::from("a"), runner(token("hunter2")));
+        config.update().await.unwrap();
+
+        config.config.runners.remove("a");
+        config.update().await.unwrap();
+
+        assert!(store.get("runner-a").unwrap().is_none());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn legacy_credentials_are_migrated() {
+        let dir = temp_dir();
+        let store = Arc::new(MemoryStore::default());
+
+        std::fs::write(
+            dir.join(CONF_FILE_NAME),
+            r#"{
+                "runners": {
+                    "a": {
+                        "name": "Runner",
+                        "url": "http://localhost:8080",
+                        "auth": { "token": { "token": "hunter2" } }
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let config = ConfigFile::open(dir.clone(), store.clone()).await.unwrap();
+        assert_eq!(config.config.runners["a"].auth, token("hunter2"));
+
+        let raw = std::fs::read_to_string(dir.join(CONF_FILE_NAME)).unwrap();
+        assert!(!raw.contains("hunter2"));
+        assert!(store.get("runner-a").unwrap().is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
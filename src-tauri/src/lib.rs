@@ -1,5 +1,5 @@
 use std::{collections::HashMap, sync::Arc};
-use tauri::Manager;
+use tauri::{Manager, RunEvent};
 use tauri_plugin_sentry::sentry;
 use tokio::sync::Mutex;
 use tracing::{debug, error};
@@ -7,12 +7,15 @@ use tracing::{debug, error};
 mod cmd;
 mod config;
 mod runner;
+mod secret;
+mod template;
 
 use config::ConfigFile;
 
 const DEBUG_MODE_VAR: &str = "VK_DEBUG";
 
 pub struct AppState {
+    config: Arc<Mutex<ConfigFile>>,
     runners: Arc<Mutex<HashMap<String, Arc<runner::Runner>>>>,
     sentry_guard: Arc<Mutex<Option<sentry::ClientInitGuard>>>,
 }
@@ -28,7 +31,7 @@ pub async fn run() {
         }})
         .init();
 
-    let _config = match ConfigFile::new().await {
+    let config = match ConfigFile::new().await {
         Ok(o) => o,
         Err(e) => {
             error!("Failed to initiate config: {}", e);
@@ -37,6 +40,7 @@ pub async fn run() {
     };
 
     let state = AppState {
+        config: Arc::new(Mutex::new(config)),
         runners: Arc::new(Mutex::new(HashMap::new())),
         sentry_guard: Arc::new(Mutex::new(None)),
     };
@@ -68,35 +72,48 @@ pub async fn run() {
         .setup(|app| {
             app.manage(state);
 
+            let app_handle = Arc::new(app.handle().clone());
+            tokio::spawn(async move {
+                cmd::runner::restore_runners(app_handle).await;
+            });
+
             Ok(())
         })
         .invoke_handler(tauri::generate_handler![
             app_version,
             host_platform,
+            cmd::console::console_close,
+            cmd::console::console_open,
+            cmd::console::instance_input_history,
+            cmd::console::send_instance_input,
             cmd::instance::del_instance,
             cmd::instance::new_instance,
+            cmd::instance::new_instance_upload,
+            cmd::instance::pick_upload_file,
             cmd::instance::start_instance,
             cmd::instance::stop_instance,
+            cmd::instance::validate_instance,
             cmd::misc::is_valid_url,
             cmd::runner::runner_info,
             cmd::runner::runner_list,
             cmd::runner::runner_new,
+            cmd::runner::runner_reconnect,
+            cmd::runner::runner_remove,
+            cmd::runner::runner_update,
+            cmd::stats::instance_stats,
+            cmd::stats::stats_unwatch,
+            cmd::stats::stats_watch,
+            cmd::template::instance_from_template,
+            cmd::template::template_delete,
+            cmd::template::template_export,
+            cmd::template::template_import,
+            cmd::template::template_list,
+            cmd::template::template_save,
         ])
-        .run(tauri::generate_context!());
-    
-        match r {
-            Ok(_) => {}
-            Err(e) => {
-                error!(
-                    "An error occurred while Volkanic Console was running: {}",
-                    e
-                );
-                std::process::exit(1);
-            }
-        }
+        .build(tauri::generate_context!());
 
-    match r {
-        Ok(_) => {}
+    let app = match r {
+        Ok(o) => o,
         Err(e) => {
             error!(
                 "An error occurred while Volkanic Console was running: {}",
@@ -104,7 +121,28 @@ pub async fn run() {
             );
             std::process::exit(1);
         }
-    }
+    };
+
+    // Set once the runners are being shut down, the exit request issued
+    // afterwards is let through
+    let mut shutting_down = false;
+
+    app.run(move |app_handle, event| {
+        if let RunEvent::ExitRequested { code, api, .. } = event {
+            if shutting_down {
+                return;
+            }
+
+            shutting_down = true;
+            api.prevent_exit();
+
+            let app_handle = app_handle.clone();
+            tauri::async_runtime::spawn(async move {
+                cmd::runner::shutdown_runners(&app_handle).await;
+                app_handle.exit(code.unwrap_or(0));
+            });
+        }
+    });
 }
 
 #[tauri::command]
@@ -0,0 +1,69 @@
+use reqwest::{Response, StatusCode};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+use super::{
+    http::{new_client, REQUEST_TIMEOUT_MS},
+    tls::TlsSettings,
+    Error,
+};
+
+/// Credentials used to authenticate with a runner
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum RunnerAuth {
+    #[default]
+    #[serde(rename = "none")]
+    None,
+    /// Token sent as-is on every request
+    #[serde(rename = "token")]
+    Token { token: String },
+    /// Username and password exchanged for a session token
+    #[serde(rename = "login")]
+    Login { username: String, password: String },
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct LoginRequest<'a> {
+    username: &'a str,
+    password: &'a str,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+struct LoginResponse {
+    token: String,
+}
+
+/// Exchanges a username and password for a session token
+pub async fn login(
+    url: &str,
+    tls: &TlsSettings,
+    username: &str,
+    password: &str,
+) -> Result<String, Error> {
+    let client = new_client(tls)?;
+
+    let r = client
+        .post(format!("{}/login", url))
+        .timeout(Duration::from_millis(REQUEST_TIMEOUT_MS))
+        .json(&LoginRequest { username, password })
+        .send()
+        .await
+        .map_err(Error::Http)?;
+
+    let r = check_authorized(r)?;
+
+    let login = r
+        .json::<LoginResponse>()
+        .await
+        .map_err(|_| Error::ResponseDecode)?;
+
+    Ok(login.token)
+}
+
+/// Fails with `Error::Unauthorized` if the runner rejected the credentials
+pub fn check_authorized(r: Response) -> Result<Response, Error> {
+    match r.status() {
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN => Err(Error::Unauthorized),
+        _ => Ok(r),
+    }
+}
@@ -0,0 +1,138 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use tokio::time;
+
+use super::Runner;
+
+/// Reconnection delays used after failed heartbeats and dropped streams
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BackoffPolicy {
+    /// Delay after the first failure in milliseconds
+    #[serde(alias = "initial_ms")]
+    pub initial_ms: u64,
+    /// Upper bound of the delay in milliseconds
+    #[serde(alias = "max_ms")]
+    pub max_ms: u64,
+    /// Factor the delay grows by after every failure
+    pub multiplier: f64,
+    /// Fraction of the delay that is randomized, between 0 and 1
+    pub jitter: f64,
+}
+
+impl Default for BackoffPolicy {
+    fn default() -> Self {
+        Self {
+            initial_ms: 1000,
+            max_ms: 60000,
+            multiplier: 2.0,
+            jitter: 0.2,
+        }
+    }
+}
+
+/// Failures in a row of a single reconnecting loop
+#[derive(Debug, Clone, Default)]
+pub struct Backoff {
+    attempt: u32,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Returns the delay before the next attempt and counts the failure.
+    /// The delay is capped first and then only jittered downwards, so
+    /// delays at the cap still differ between runners.
+    pub fn next_delay(&mut self, policy: &BackoffPolicy) -> Duration {
+        let max = policy.max_ms.max(1) as f64;
+        let base = (policy.initial_ms as f64 * policy.multiplier.max(1.0).powi(self.attempt as i32)).min(max);
+
+        let jitter = policy.jitter.clamp(0.0, 1.0) * base;
+        let delay = if jitter > 0.0 {
+            base - rand::thread_rng().gen_range(0.0..=jitter)
+        } else {
+            base
+        };
+
+        self.attempt = self.attempt.saturating_add(1);
+
+        Duration::from_millis(delay as u64)
+    }
+    /// Starts over with the initial delay, called after a success
+    pub fn reset(&mut self) {
+        self.attempt = 0;
+    }
+}
+
+impl Runner {
+    /// Waits before the next reconnection attempt. Returns early and
+    /// starts over with the initial delay if a reconnect is requested.
+    ///
+    /// `min` is a lower bound for the delay, e.g. a retry interval sent
+    /// by the runner.
+    pub(super) async fn backoff(&self, backoff: &mut Backoff, min: Option<Duration>) {
+        let policy = self.details.lock().await.backoff.clone();
+
+        let mut delay = backoff.next_delay(&policy);
+        if let Some(min) = min {
+            delay = delay.max(min);
+        }
+
+        tokio::select! {
+            _ = time::sleep(delay) => {}
+            _ = self.reconnect.notified() => {
+                backoff.reset();
+            }
+        }
+    }
+    /// Cuts short all pending reconnection delays
+    pub fn reconnect(&self) {
+        self.reconnect.notify_waiters();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn delays_at_the_cap_are_jittered() {
+        let policy = BackoffPolicy::default();
+        let mut backoff = Backoff::new();
+
+        // Reaches the cap after a few attempts
+        let delays: Vec<u64> = (0..50).map(|_| backoff.next_delay(&policy).as_millis() as u64).collect();
+        let capped = &delays[10..];
+
+        assert!(delays.iter().all(|d| *d <= policy.max_ms));
+        assert!(capped.iter().all(|d| *d as f64 >= policy.max_ms as f64 * (1.0 - policy.jitter)));
+        assert!(capped.iter().any(|d| *d != capped[0]));
+    }
+
+    #[test]
+    fn accepts_camel_and_snake_case_keys() {
+        let camel: BackoffPolicy = serde_json::from_str(r#"{ "initialMs": 500, "maxMs": 5000 }"#).unwrap();
+        let snake: BackoffPolicy = serde_json::from_str(r#"{ "initial_ms": 500, "max_ms": 5000 }"#).unwrap();
+
+        assert_eq!(camel, snake);
+        assert_eq!(camel.initial_ms, 500);
+        assert_eq!(camel.jitter, BackoffPolicy::default().jitter);
+    }
+
+    #[test]
+    fn delays_grow_without_jitter() {
+        let policy = BackoffPolicy {
+            jitter: 0.0,
+            ..BackoffPolicy::default()
+        };
+        let mut backoff = Backoff::new();
+
+        let delays: Vec<u64> = (0..8).map(|_| backoff.next_delay(&policy).as_millis() as u64).collect();
+        assert_eq!(delays, [1000, 2000, 4000, 8000, 16000, 32000, 60000, 60000]);
+
+        backoff.reset();
+        assert_eq!(backoff.next_delay(&policy), Duration::from_millis(1000));
+    }
+}
@@ -0,0 +1,40 @@
+use tokio::sync::broadcast;
+
+use super::{instance::Instance, Runner};
+
+/// A change to the local state of a runner
+#[derive(Debug, Clone)]
+pub enum RunnerChange {
+    /// Name or connection details changed
+    Details,
+    /// Connection status or quality changed
+    Connection,
+    Health,
+    InstanceUpserted { id: String, instance: Box<Instance> },
+    InstanceRemoved { id: String },
+    /// All instances were replaced, e.g. after pulling them from the runner
+    Instances,
+}
+
+impl Runner {
+    /// Returns a receiver for changes to the runner. Receivers that lag
+    /// behind should resync from a full snapshot.
+    pub fn subscribe_changes(&self) -> broadcast::Receiver<RunnerChange> {
+        self.update.subscribe()
+    }
+    pub(super) fn send_change(&self, change: RunnerChange) {
+        let _ = self.update.send(change);
+    }
+    /// Sends the current state of a single instance
+    pub(super) async fn send_instance(&self, id: &str) {
+        let instance = self.instances.lock().await.get(id).cloned();
+
+        match instance {
+            Some(instance) => self.send_change(RunnerChange::InstanceUpserted {
+                id: id.to_string(),
+                instance: Box::new(instance),
+            }),
+            None => self.send_change(RunnerChange::InstanceRemoved { id: id.to_string() }),
+        }
+    }
+}
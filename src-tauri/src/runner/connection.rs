@@ -0,0 +1,136 @@
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Consecutive failed heartbeats after which the runner is offline
+const OFFLINE_AFTER_FAILURES: u32 = 3;
+/// Smoothed round-trip time above which the runner is degraded
+const DEGRADED_LATENCY_MS: u64 = 1000;
+/// Weight of the latest round-trip time in the smoothed latency, in percent
+const LATENCY_SMOOTHING: u64 = 30;
+/// Minimum time between updates that don't change the status, in
+/// milliseconds
+const CONNECTION_UPDATE_INTERVAL_MS: u64 = 10000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum ConnectionStatus {
+    #[serde(rename = "online")]
+    Online,
+    /// Reachable, but slow or failing some heartbeats
+    #[serde(rename = "degraded")]
+    Degraded,
+    #[serde(rename = "offline")]
+    Offline,
+}
+
+/// Connection quality, measured by the heartbeat
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Connection {
+    pub status: ConnectionStatus,
+    /// Smoothed heartbeat round-trip time in milliseconds
+    pub latency_ms: Option<u64>,
+    /// Heartbeats failed in a row
+    pub failures: u32,
+    /// Unix timestamp in milliseconds of the last successful heartbeat
+    pub last_seen: Option<u64>,
+    /// When the last update was sent to listeners
+    #[serde(skip)]
+    sent: Option<Instant>,
+}
+
+impl Connection {
+    pub(super) fn new(online: bool) -> Self {
+        Self {
+            status: if online { ConnectionStatus::Online } else { ConnectionStatus::Offline },
+            latency_ms: None,
+            failures: 0,
+            last_seen: None,
+            sent: None,
+        }
+    }
+    pub fn is_connected(&self) -> bool {
+        self.status != ConnectionStatus::Offline
+    }
+    /// Records a successful heartbeat with its round-trip time
+    pub(super) fn record_success(&mut self, rtt: Duration) {
+        let rtt = rtt.as_millis() as u64;
+
+        let latency = match self.latency_ms {
+            Some(latency) if self.failures == 0 => {
+                (latency * (100 - LATENCY_SMOOTHING) + rtt * LATENCY_SMOOTHING) / 100
+            }
+            _ => rtt,
+        };
+
+        self.latency_ms = Some(latency);
+        self.failures = 0;
+        self.last_seen = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .ok();
+        self.status = if latency > DEGRADED_LATENCY_MS {
+            ConnectionStatus::Degraded
+        } else {
+            ConnectionStatus::Online
+        };
+    }
+    /// Records a failed heartbeat. A single failure only degrades the
+    /// connection, so flaky links don't flicker between online and offline.
+    pub(super) fn record_failure(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+
+        if self.failures >= OFFLINE_AFTER_FAILURES {
+            self.status = ConnectionStatus::Offline;
+            self.latency_ms = None;
+        } else if self.status == ConnectionStatus::Online {
+            self.status = ConnectionStatus::Degraded;
+        }
+    }
+    /// Whether listeners should be updated after a heartbeat. Status
+    /// changes are sent right away, the latency and last-seen time change
+    /// on every heartbeat and are sent at most every
+    /// `CONNECTION_UPDATE_INTERVAL_MS`.
+    pub(super) fn should_send(&mut self, status_changed: bool, now: Instant) -> bool {
+        let due = match self.sent {
+            Some(sent) => now.duration_since(sent) >= Duration::from_millis(CONNECTION_UPDATE_INTERVAL_MS),
+            None => true,
+        };
+
+        if status_changed || due {
+            self.sent = Some(now);
+        }
+
+        status_changed || due
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INTERVAL: Duration = Duration::from_millis(CONNECTION_UPDATE_INTERVAL_MS);
+
+    #[test]
+    fn status_changes_are_sent_right_away() {
+        let start = Instant::now();
+        let mut connection = Connection::new(true);
+
+        assert!(connection.should_send(false, start));
+
+        connection.record_failure();
+        assert_eq!(connection.status, ConnectionStatus::Degraded);
+        assert!(connection.should_send(true, start + Duration::from_millis(1)));
+    }
+
+    #[test]
+    fn other_changes_are_throttled() {
+        let start = Instant::now();
+        let mut connection = Connection::new(true);
+
+        assert!(connection.should_send(false, start));
+
+        connection.record_success(Duration::from_millis(20));
+        assert!(!connection.should_send(false, start + INTERVAL / 2));
+        assert!(connection.should_send(false, start + INTERVAL));
+        assert!(!connection.should_send(false, start + INTERVAL + INTERVAL / 2));
+    }
+}
@@ -0,0 +1,227 @@
+use futures_util::StreamExt;
+use reqwest::{header::ACCEPT, Method};
+use serde::{Deserialize, Serialize};
+use std::{collections::VecDeque, sync::Arc};
+use tokio::{sync::broadcast, task::JoinHandle};
+use tracing::{debug, error};
+
+use super::{
+    backoff::Backoff,
+    http::check_status,
+    protocol::Capability,
+    sse::{SseDecoder, LAST_EVENT_ID_HEADER},
+    Error,
+    Runner,
+};
+
+/// Lines kept per instance
+const CONSOLE_BUFFER_LINES: usize = 1000;
+/// Commands kept in the input history per instance
+const INPUT_HISTORY_LEN: usize = 100;
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ConsoleLine {
+    pub stream: OutputStream,
+    pub text: String,
+    /// Unix timestamp in milliseconds
+    #[serde(default)]
+    pub time: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum OutputStream {
+    #[serde(rename = "stdout")]
+    Stdout,
+    #[serde(rename = "stderr")]
+    Stderr,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct InputRequest<'a> {
+    input: &'a str,
+}
+
+/// Console output of a single instance
+#[derive(Default)]
+pub struct Console {
+    lines: VecDeque<ConsoleLine>,
+    /// Set while the console is being streamed from the runner
+    task: Option<JoinHandle<()>>,
+}
+
+impl Console {
+    fn push(&mut self, line: ConsoleLine) {
+        if self.lines.len() >= CONSOLE_BUFFER_LINES {
+            self.lines.pop_front();
+        }
+
+        self.lines.push_back(line);
+    }
+}
+
+impl Runner {
+    /// Pulls the console history of an instance from the runner
+    pub async fn console_history(&self, id: &str) -> Result<Vec<ConsoleLine>, Error> {
+        let request = self.request(Method::GET, &format!("/instance/{}/console", id)).await?;
+
+        check_status(self.send(request).await?)
+            .await?
+            .json::<Vec<ConsoleLine>>()
+            .await
+            .map_err(|_| Error::ResponseDecode)
+    }
+    /// Starts streaming the console of an instance and returns the lines
+    /// received so far
+    pub async fn open_console(self: &Arc<Self>, id: &str) -> Result<Vec<ConsoleLine>, Error> {
+        self.require(Capability::Console).await?;
+
+        if let Some(console) = self.consoles.lock().await.get(id) {
+            if console.task.is_some() {
+                return Ok(console.lines.iter().cloned().collect());
+            }
+        }
+
+        let history = self.console_history(id).await?;
+
+        let mut consoles = self.consoles.lock().await;
+        let console = consoles.entry(id.to_string()).or_default();
+
+        console.lines.clear();
+        for line in history {
+            console.push(line);
+        }
+
+        if console.task.is_none() {
+            console.task = Some(self.stream_console(id.to_string()));
+        }
+
+        Ok(console.lines.iter().cloned().collect())
+    }
+    /// Stops streaming the console of an instance
+    pub async fn close_console(&self, id: &str) {
+        if let Some(console) = self.consoles.lock().await.remove(id) {
+            if let Some(task) = console.task {
+                task.abort();
+            }
+
+            debug!("Closed console of instance {}", id);
+        }
+    }
+    /// Sends a line of input to the stdin of an instance
+    pub async fn send_instance_input(&self, id: &str, input: &str) -> Result<(), Error> {
+        self.require(Capability::Input).await?;
+
+        let input = input.trim_end_matches(['\r', '\n']);
+
+        let request = self.request(Method::POST, &format!("/instance/{}/input", id)).await?;
+
+        check_status(self.send(request.json(&InputRequest { input })).await?).await?;
+
+        // Only recorded once the runner accepted it
+        {
+            let mut history = self.input_history.lock().await;
+            let history = history.entry(id.to_string()).or_default();
+
+            // Repeating the last command doesn't add it again
+            if !input.is_empty() && history.back().map(|s| s.as_str()) != Some(input) {
+                if history.len() >= INPUT_HISTORY_LEN {
+                    history.pop_front();
+                }
+
+                history.push_back(input.to_string());
+            }
+        }
+
+        Ok(())
+    }
+    /// Returns the commands sent to an instance, oldest first
+    pub async fn input_history(&self, id: &str) -> Vec<String> {
+        match self.input_history.lock().await.get(id) {
+            Some(history) => history.iter().cloned().collect(),
+            None => Vec::new(),
+        }
+    }
+    /// Returns a receiver for console lines of all instances, as
+    /// (instance ID, line)
+    pub fn subscribe_console(&self) -> broadcast::Receiver<(String, ConsoleLine)> {
+        self.console_tx.subscribe()
+    }
+    /// Stops streaming all consoles
+    pub(super) async fn close_consoles(&self) {
+        for (_, console) in self.consoles.lock().await.drain() {
+            if let Some(task) = console.task {
+                task.abort();
+            }
+        }
+    }
+    fn stream_console(self: &Arc<Self>, id: String) -> JoinHandle<()> {
+        let runner = self.clone();
+
+        self.spawn_cancellable(async move {
+            let mut decoder = SseDecoder::new();
+            let mut backoff = Backoff::new();
+
+            loop {
+                let path = format!("/instance/{}/console/events", id);
+
+                let r = match runner.stream_request(Method::GET, &path).await {
+                    Ok(request) => {
+                        let mut request = request.header(ACCEPT, "text/event-stream");
+
+                        if let Some(last_id) = decoder.last_event_id() {
+                            request = request.header(LAST_EVENT_ID_HEADER, last_id);
+                        }
+
+                        runner.send(request).await
+                    }
+                    Err(e) => Err(e),
+                };
+
+                let r = match r {
+                    Ok(r) => check_status(r).await,
+                    Err(e) => Err(e),
+                };
+
+                let mut stream = match r {
+                    Ok(r) => r.bytes_stream(),
+                    Err(e) => {
+                        error!("Got error requesting console of instance {}: {}", id, e);
+                        runner.backoff(&mut backoff, decoder.retry()).await;
+                        continue;
+                    }
+                };
+
+                backoff.reset();
+                decoder = decoder.resume();
+
+                while let Some(chunk) = stream.next().await {
+                    let chunk = match chunk {
+                        Ok(o) => o,
+                        Err(e) => {
+                            error!("An error occurred while receiving console output: {e}");
+                            break;
+                        }
+                    };
+
+                    for event in decoder.feed(&chunk) {
+                        let line = match serde_json::from_str::<ConsoleLine>(&event.data) {
+                            Ok(o) => o,
+                            Err(e) => {
+                                error!("An error occurred while parsing console line: {e}");
+                                continue;
+                            }
+                        };
+
+                        if let Some(console) = runner.consoles.lock().await.get_mut(&id) {
+                            console.push(line.clone());
+                        }
+
+                        let _ = runner.console_tx.send((id.clone(), line));
+                    }
+                }
+
+                runner.backoff(&mut backoff, decoder.retry()).await;
+            }
+        })
+    }
+}
@@ -1,66 +1,110 @@
 use futures_util::StreamExt;
+use reqwest::{header::{ACCEPT, CONTENT_TYPE}, Method};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
 use std::sync::Arc;
-use tracing::{debug, error};
+use tokio::task::JoinHandle;
+use tracing::{debug, error, warn};
 
 use super::{
-    http::new_client,
+    backoff::Backoff,
+    change::RunnerChange,
+    http::check_status,
     instance::Instance,
+    protocol::Capability,
+    sse::{SseDecoder, SseEvent, LAST_EVENT_ID_HEADER},
+    stats::InstanceStats,
     Runner
 };
 
-const RETRY_INTERVAL_MS: u64 = 1000;
-
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum RemoteEvent {
+    /// The instance is decoded separately, see `Instance::decode`
     #[serde(rename = "modify-instance")]
-    ModifyInstance { id: String, instance: Instance },
+    ModifyInstance { id: String, instance: Value },
     #[serde(rename = "delete-instance")]
     DeleteInstance { id: String },
+    #[serde(rename = "instance-stats")]
+    InstanceStats { id: String, stats: InstanceStats },
 }
 
-pub async fn event_listen(runner: Arc<Runner>) {
-    let url = format!("{}/events", runner.details.lock().await.url.clone());
+pub fn event_listen(runner: Arc<Runner>) -> JoinHandle<()> {
+    runner.clone().spawn_cancellable(async move {
+        // Kept across reconnections for the last event ID and retry interval
+        let mut decoder = SseDecoder::new();
+        let mut backoff = Backoff::new();
+        // Kept across reconnections so no status change is missed
+        let mut status = runner.subscribe_status();
 
-    tokio::task::spawn(async move {
         loop {
-            let url = url.clone();
+            debug!("Waiting for connection");
 
-            loop {
-                debug!("Checking if connected");
-                let connected = *runner.connected.lock().await;
+            if status.wait_for(|connected| *connected).await.is_err() {
+                debug!("Status channel closed, stopping event listener");
+                return;
+            }
 
-                if connected {
-                    debug!("Connected, starting event listener");
-                    break;
-                }
+            if let Err(e) = runner.require(Capability::Events).await {
+                warn!("Not listening for events: {}", e);
 
-                debug!("Not connected, waiting for connection");
+                // Negotiated again once the runner reconnects
+                if status.changed().await.is_err() {
+                    return;
+                }
 
-                runner.wait_for_status().await;
+                continue;
             }
 
-            let client = match new_client() {
+            debug!("Connected, starting event listener");
+
+            // Failures are retried since the connection details may be corrected
+            let request = match runner.stream_request(Method::GET, "/events").await {
                 Ok(o) => o,
                 Err(e) => {
-                    error!("Got error while creating client for SSE listener: {}", e);
-                    break;
-                },
+                    error!("Got error while creating SSE request: {}", e);
+                    wait(&runner, &mut backoff, &decoder).await;
+                    continue;
+                }
             };
 
             debug!("Sending SSE request");
 
-            let r = match client
-                .get(url)
-                .send().await {
+            let mut request = request.header(ACCEPT, "text/event-stream");
+
+            if let Some(id) = decoder.last_event_id() {
+                debug!("Resuming from event ID {}", id);
+                request = request.header(LAST_EVENT_ID_HEADER, id);
+            }
+
+            // Error responses must not be fed into the decoder
+            let r = match runner.send(request).await {
+                Ok(o) => check_status(o).await,
+                Err(e) => Err(e),
+            };
+
+            let r = match r {
                 Ok(o) => o,
                 Err(e) => {
                     error!("Got error requesting SSE: {}", e);
-                    wait().await;
+                    wait(&runner, &mut backoff, &decoder).await;
                     continue;
                 }
             };
 
+            // Runners that don't send a content type are trusted
+            let content_type = r
+                .headers()
+                .get(CONTENT_TYPE)
+                .and_then(|t| t.to_str().ok())
+                .filter(|t| !t.starts_with("text/event-stream"))
+                .map(|t| t.to_string());
+
+            if let Some(content_type) = content_type {
+                error!("Runner responded with {} instead of an event stream", content_type);
+                wait(&runner, &mut backoff, &decoder).await;
+                continue;
+            }
+
             debug!("SSE request succeeded");
             debug!("Refreshing everything");
 
@@ -68,79 +112,85 @@ pub async fn event_listen(runner: Arc<Runner>) {
                 Ok(_) => {},
                 Err(e) => {
                     error!("An error occurred while updating all instances: {}", e);
-                    wait().await;
+                    wait(&runner, &mut backoff, &decoder).await;
                     continue;
                 }
             };
-            
+
+            backoff.reset();
+            decoder = decoder.resume();
             let mut stream = r.bytes_stream();
 
             loop {
                 tokio::select! {
-                    _ = runner.wait_for_status() => {
+                    r = status.changed() => {
+                        if r.is_err() {
+                            return;
+                        }
+
                         // This forces a full refresh and reconnection
                         break;
                     }
-                    event_raw = stream.next() => {
-                        let event_raw = match event_raw {
+                    chunk = stream.next() => {
+                        let chunk = match chunk {
                             Some(Ok(o)) => o,
                             Some(Err(e)) => {
                                 error!("An error occurred while receiving event: {e}");
+                                wait(&runner, &mut backoff, &decoder).await;
                                 break;
                             },
-                            None => break,
-                        };
-
-                        debug!("Received remote event");
-
-                        // Decode the raw event bytes into a UTF8 string
-                        let mut event_str = match std::str::from_utf8(&event_raw) {
-                            Ok(o) => o,
-                            Err(e) => {
-                                error!("An error occurred while decoding UTF8 in event: {e}");
-                                break;
-                            }
-                        };
-
-                        debug!("Got remote raw event: {}", event_str);
-
-                        // Check for the "data: " prefix
-                        if event_str.starts_with("data: ") {
-                            debug!("Stripped prefix from event string, raw: {}", event_str);
-                            // Strips the prefix from the raw event string
-                            event_str = &event_str[6..];
-                        } else {
-                            error!("Received non-data in event listener");
-                            break;
-                        }
-
-                        let event = match serde_json::from_str::<RemoteEvent>(event_str) {
-                            Ok(o) => o,
-                            Err(e) => {
-                                error!("An error occurred while parsing remote event: {e}");
+                            None => {
+                                debug!("Event stream closed by runner");
+                                wait(&runner, &mut backoff, &decoder).await;
                                 break;
-                            }
+                            },
                         };
 
-                        match event {
-                            RemoteEvent::ModifyInstance { id, instance } => {
-                                debug!("Got remote modify event (ID: {}, Instance: {:?})", id, instance);
-                                runner.instances.lock().await.insert(id, instance);
-                                runner.send_update();
-                            }
-                            RemoteEvent::DeleteInstance { id } => {
-                                debug!("Got remote delete event (ID: {})", id);
-                                runner.instances.lock().await.remove(&id);
-                                runner.send_update();
-                            }
+                        for event in decoder.feed(&chunk) {
+                            handle_event(&runner, event).await;
                         }
                     }
                 }
             }
         }
-    });
+    })
+}
+
+async fn handle_event(runner: &Runner, event: SseEvent) {
+    debug!("Got remote raw event ({}): {}", event.event, event.data);
+
+    let event = match serde_json::from_str::<RemoteEvent>(&event.data) {
+        Ok(o) => o,
+        Err(e) => {
+            error!("An error occurred while parsing remote event: {e}");
+            return;
+        }
+    };
+
+    match event {
+        RemoteEvent::ModifyInstance { id, instance } => {
+            let instance = Instance::decode(instance);
+            debug!("Got remote modify event (ID: {}, Instance: {:?})", id, instance);
+            runner.confirm_transition(&id).await;
+            runner.instances.lock().await.insert(id.clone(), instance.clone());
+            runner.send_change(RunnerChange::InstanceUpserted { id, instance: Box::new(instance) });
+        }
+        RemoteEvent::DeleteInstance { id } => {
+            debug!("Got remote delete event (ID: {})", id);
+            runner.confirm_transition(&id).await;
+            runner.close_console(&id).await;
+            runner.unwatch_stats(&id).await;
+            runner.stats.lock().await.remove(&id);
+            runner.instances.lock().await.remove(&id);
+            runner.send_change(RunnerChange::InstanceRemoved { id });
+        }
+        RemoteEvent::InstanceStats { id, stats } => {
+            runner.push_stats(&id, stats).await;
+        }
+    }
 }
 
-async fn wait() {
-    tokio::time::sleep(tokio::time::Duration::from_millis(RETRY_INTERVAL_MS)).await;
+/// Waits before reconnecting, at least as long as the runner asked for
+async fn wait(runner: &Runner, backoff: &mut Backoff, decoder: &SseDecoder) {
+    runner.backoff(backoff, decoder.retry()).await;
 }
@@ -0,0 +1,133 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use tracing::debug;
+
+use super::{
+    change::RunnerChange,
+    http::check_status,
+    instance::InstanceStatus,
+    protocol::Protocol,
+    Error,
+    Runner,
+};
+
+/// Health of the runner and its host, as reported by `/info`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RunnerHealth {
+    pub version: String,
+    /// Runner uptime in seconds
+    #[serde(default)]
+    pub uptime: Option<u64>,
+    #[serde(default)]
+    pub host: Option<HostHealth>,
+    #[serde(default)]
+    pub instances: Option<InstanceCounts>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize, Serialize)]
+pub struct HostHealth {
+    /// Load averages over 1, 5 and 15 minutes
+    #[serde(default)]
+    pub load: Vec<f64>,
+    #[serde(default)]
+    pub cpus: Option<u64>,
+    /// Used memory in bytes
+    #[serde(default)]
+    pub memory_used: Option<u64>,
+    /// Total memory in bytes
+    #[serde(default)]
+    pub memory_total: Option<u64>,
+    /// Used disk space in bytes
+    #[serde(default)]
+    pub disk_used: Option<u64>,
+    /// Total disk space in bytes
+    #[serde(default)]
+    pub disk_total: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct InstanceCounts {
+    pub total: u64,
+    pub running: u64,
+}
+
+/// Minimum time between updates for values that change on every report
+const HEALTH_UPDATE_INTERVAL_MS: u64 = 30000;
+
+impl RunnerHealth {
+    /// Compares everything except values that change on every report,
+    /// like the uptime, load and used memory
+    fn same_stable(&self, other: &Self) -> bool {
+        let host = |h: &Self| h.host.as_ref().map(|h| (h.cpus, h.memory_total, h.disk_total));
+
+        self.version == other.version && self.instances == other.instances && host(self) == host(other)
+    }
+}
+
+impl Runner {
+    /// Returns the last health report, if the runner was reachable
+    pub async fn get_health(&self) -> Option<RunnerHealth> {
+        self.health.lock().await.clone()
+    }
+    /// Pulls the health report from the runner. Updates are sent right
+    /// away for significant changes, otherwise at most every
+    /// `HEALTH_UPDATE_INTERVAL_MS`.
+    pub(super) async fn refresh_health(&self) -> Result<(), Error> {
+        let request = self.request(Method::GET, "/info").await?;
+
+        let info = check_status(self.send(request).await?)
+            .await?
+            .json::<Value>()
+            .await
+            .map_err(|_| Error::ResponseDecode)?;
+
+        self.set_protocol(Protocol::from_info(&info)?).await;
+
+        let mut health =
+            serde_json::from_value::<RunnerHealth>(info).map_err(|_| Error::ResponseDecode)?;
+
+        // Older runners don't report instance counts, use the local ones
+        if health.instances.is_none() {
+            let instances = self.instances.lock().await;
+
+            health.instances = Some(InstanceCounts {
+                total: instances.len() as u64,
+                running: instances
+                    .values()
+                    .filter(|i| matches!(i.status, InstanceStatus::Running))
+                    .count() as u64,
+            });
+        }
+
+        let (changed, significant) = {
+            let mut current = self.health.lock().await;
+            let changed = current.as_ref() != Some(&health);
+            let significant = !current.as_ref().is_some_and(|c| c.same_stable(&health));
+
+            *current = Some(health);
+
+            (changed, significant)
+        };
+
+        let due = match *self.health_sent.lock().await {
+            Some(sent) => sent.elapsed() >= Duration::from_millis(HEALTH_UPDATE_INTERVAL_MS),
+            None => true,
+        };
+
+        if significant || (changed && due) {
+            debug!("Runner health changed");
+            *self.health_sent.lock().await = Some(Instant::now());
+            self.send_change(RunnerChange::Health);
+        }
+
+        Ok(())
+    }
+    /// Forgets the health report, e.g. once the runner went offline
+    pub(super) async fn clear_health(&self) {
+        if self.health.lock().await.take().is_some() {
+            self.send_change(RunnerChange::Health);
+        }
+    }
+}
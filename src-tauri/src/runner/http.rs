@@ -1,15 +1,54 @@
-use reqwest::Client;
+use reqwest::{Client, Response, StatusCode};
+use serde::Deserialize;
 use std::time::Duration;
 use url::Url;
 
+use super::{tls::{self, TlsSettings}, Error};
+
 // Connection timeout in milliseconds
 const DEFAULT_TIMEOUT_MS: u64 = 4000;
+// Total request timeout in milliseconds, not applied to streams
+pub const REQUEST_TIMEOUT_MS: u64 = 10000;
 
-pub fn new_client() -> Result<Client, reqwest::Error> {
-    Client::builder()
+pub fn new_client(tls: &TlsSettings) -> Result<Client, Error> {
+    let builder = Client::builder()
         .connect_timeout(Duration::from_millis(DEFAULT_TIMEOUT_MS))
-        .user_agent(user_agent())
-        .build()
+        .user_agent(user_agent());
+
+    tls::apply(builder, tls)?.build().map_err(Error::Http)
+}
+
+/// Error payload returned by the runner
+#[derive(Debug, Clone, Deserialize)]
+struct RemoteError {
+    #[serde(alias = "message")]
+    error: String,
+}
+
+/// Turns non-success responses into errors, using the error message
+/// from the runner if it sent one
+pub async fn check_status(r: Response) -> Result<Response, Error> {
+    let status = r.status();
+
+    if status.is_success() {
+        return Ok(r);
+    }
+
+    let body = r.text().await.unwrap_or_default();
+
+    let message = match serde_json::from_str::<RemoteError>(&body) {
+        Ok(o) => o.error,
+        Err(_) if !body.trim().is_empty() => body.trim().to_string(),
+        Err(_) => status.canonical_reason().unwrap_or("Unknown error").to_string(),
+    };
+
+    Err(match status {
+        StatusCode::NOT_FOUND => Error::NotFound(message),
+        StatusCode::CONFLICT => Error::Conflict(message),
+        StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY => Error::InvalidState(message),
+        s if s.is_server_error() => Error::Server(message),
+        s => Error::UnexpectedStatus(s.as_u16(), message),
+    })
 }
 
 pub fn is_valid_url<T: std::fmt::Display>(url: T) -> bool {
@@ -1,4 +1,7 @@
-use serde::{Deserialize, Serialize};
+use serde::{de, de::DeserializeOwned, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+use tracing::warn;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Instance {
@@ -6,15 +9,165 @@ pub struct Instance {
     #[serde(rename = "type")]
     pub inst_type: InstanceType,
     pub status: InstanceStatus,
+    /// JSON as sent by the runner, including fields this console doesn't know
+    #[serde(skip)]
+    pub raw: Value,
+    /// Set if the instance couldn't be decoded, the other fields are
+    /// filled in on a best effort basis
+    #[serde(skip)]
+    pub decode_error: Option<String>,
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+impl Instance {
+    /// Decodes an instance sent by the runner. Never fails, so a single
+    /// malformed instance doesn't hide the others.
+    pub fn decode(value: Value) -> Self {
+        match serde_json::from_value::<Instance>(value.clone()) {
+            Ok(mut instance) => {
+                instance.raw = value;
+                instance
+            }
+            Err(e) => {
+                warn!("Failed to decode instance: {}", e);
+
+                // Salvage the fields that can be decoded on their own
+                let inst_type = InstanceType::decode(&value["type"]);
+                let status = serde_json::from_value(value["status"].clone())
+                    .unwrap_or_else(|_| InstanceStatus::Unknown(tag_of(&value["status"]).unwrap_or_default()));
+
+                Self {
+                    name: value["name"].as_str().unwrap_or_default().to_string(),
+                    inst_type,
+                    status,
+                    raw: value,
+                    decode_error: Some(e.to_string()),
+                }
+            }
+        }
+    }
+}
+
+/// Returns the tag of an externally tagged enum, either `"tag"` or
+/// `{ "tag": ... }`
+fn tag_of(value: &Value) -> Option<String> {
+    match value {
+        Value::String(tag) => Some(tag.clone()),
+        Value::Object(map) if map.len() == 1 => map.keys().next().cloned(),
+        _ => None,
+    }
+}
+
+/// Decodes an externally tagged enum through `K`, a copy of it without the
+/// fallback variant. Returns the tag if it isn't one of `known`, values
+/// with a known tag but invalid content are still errors.
+fn decode_tagged<'de, D, K>(deserializer: D, known: &[&str]) -> Result<Result<K, String>, D::Error>
+where
+    D: Deserializer<'de>,
+    K: DeserializeOwned,
+{
+    let value = Value::deserialize(deserializer)?;
+
+    let tag = tag_of(&value).ok_or_else(|| de::Error::custom("expected an enum variant"))?;
+
+    if !known.contains(&tag.as_str()) {
+        return Ok(Err(tag));
+    }
+
+    serde_json::from_value(value).map(Ok).map_err(de::Error::custom)
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum InstanceType {
     #[serde(rename = "volkanic")]
     Volkanic { source: VolkanicSource },
+    #[serde(rename = "container")]
+    Container {
+        /// Image reference, e.g. `docker.io/library/nginx:latest`
+        image: String,
+        env: HashMap<String, String>,
+        ports: Vec<PortMapping>,
+        volumes: Vec<VolumeMount>,
+    },
+    /// A type this console doesn't know, only ever received from a runner
+    #[serde(skip_serializing)]
+    Unknown(String),
+}
+
+/// Same as `InstanceType` without the fallback, used for decoding
+#[derive(Deserialize)]
+enum KnownInstanceType {
+    #[serde(rename = "volkanic")]
+    Volkanic { source: VolkanicSource },
+    #[serde(rename = "container")]
+    Container {
+        image: String,
+        #[serde(default)]
+        env: HashMap<String, String>,
+        #[serde(default)]
+        ports: Vec<PortMapping>,
+        #[serde(default)]
+        volumes: Vec<VolumeMount>,
+    },
+}
+
+impl<'de> Deserialize<'de> for InstanceType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match decode_tagged(deserializer, &["volkanic", "container"])? {
+            Ok(KnownInstanceType::Volkanic { source }) => InstanceType::Volkanic { source },
+            Ok(KnownInstanceType::Container { image, env, ports, volumes }) => {
+                InstanceType::Container { image, env, ports, volumes }
+            }
+            Err(kind) => InstanceType::Unknown(kind),
+        })
+    }
+}
+
+impl InstanceType {
+    /// Decodes a type on a best effort basis, malformed types become
+    /// `Unknown`
+    pub fn decode(value: &Value) -> Self {
+        serde_json::from_value(value.clone())
+            .unwrap_or_else(|_| InstanceType::Unknown(tag_of(value).unwrap_or_default()))
+    }
+    /// Name of the type as used by the runner
+    pub fn kind(&self) -> &str {
+        match self {
+            InstanceType::Volkanic { .. } => "volkanic",
+            InstanceType::Container { .. } => "container",
+            InstanceType::Unknown(kind) => kind,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct PortMapping {
+    pub host: u16,
+    pub container: u16,
+    #[serde(default)]
+    pub protocol: PortProtocol,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub enum PortProtocol {
+    #[default]
+    #[serde(rename = "tcp")]
+    Tcp,
+    #[serde(rename = "udp")]
+    Udp,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct VolumeMount {
+    /// Host path or name of a volume managed by the runner
+    pub source: String,
+    /// Path inside the container
+    pub target: String,
+    /// Also accepted in camelCase, as the frontend sends it
+    #[serde(default, alias = "readOnly")]
+    pub read_only: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum InstanceStatus {
     #[serde(rename = "inactive")]
     Inactive,
@@ -28,14 +181,78 @@ pub enum InstanceStatus {
     Starting,
     #[serde(rename = "stopping")]
     Stopping,
+    /// A status this console doesn't know, e.g. from a newer runner
+    #[serde(skip_serializing)]
+    Unknown(String),
 }
 
-#[derive(Debug, Clone, Deserialize, Serialize)]
+/// Same as `InstanceStatus` without the fallback, used for decoding
+#[derive(Deserialize)]
+enum KnownInstanceStatus {
+    #[serde(rename = "inactive")]
+    Inactive,
+    #[serde(rename = "running")]
+    Running,
+    #[serde(rename = "creating")]
+    Creating(u8),
+    #[serde(rename = "deleting")]
+    Deleting,
+    #[serde(rename = "starting")]
+    Starting,
+    #[serde(rename = "stopping")]
+    Stopping,
+}
+
+impl<'de> Deserialize<'de> for InstanceStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let known = ["inactive", "running", "creating", "deleting", "starting", "stopping"];
+
+        Ok(match decode_tagged(deserializer, &known)? {
+            Ok(KnownInstanceStatus::Inactive) => InstanceStatus::Inactive,
+            Ok(KnownInstanceStatus::Running) => InstanceStatus::Running,
+            Ok(KnownInstanceStatus::Creating(progress)) => InstanceStatus::Creating(progress),
+            Ok(KnownInstanceStatus::Deleting) => InstanceStatus::Deleting,
+            Ok(KnownInstanceStatus::Starting) => InstanceStatus::Starting,
+            Ok(KnownInstanceStatus::Stopping) => InstanceStatus::Stopping,
+            Err(status) => InstanceStatus::Unknown(status),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub enum VolkanicSource {
     #[serde(rename = "url")]
     Url(String),
     #[serde(rename = "base64")]
     Base64(String),
+    /// ID of a file uploaded to the runner, see `Runner::upload`
+    #[serde(rename = "upload")]
+    Upload(String),
+    /// A source this console doesn't know, only ever received from a runner
+    #[serde(skip_serializing)]
+    Unknown(String),
+}
+
+/// Same as `VolkanicSource` without the fallback, used for decoding
+#[derive(Deserialize)]
+enum KnownVolkanicSource {
+    #[serde(rename = "url")]
+    Url(String),
+    #[serde(rename = "base64")]
+    Base64(String),
+    #[serde(rename = "upload")]
+    Upload(String),
+}
+
+impl<'de> Deserialize<'de> for VolkanicSource {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(match decode_tagged(deserializer, &["url", "base64", "upload"])? {
+            Ok(KnownVolkanicSource::Url(url)) => VolkanicSource::Url(url),
+            Ok(KnownVolkanicSource::Base64(encoded)) => VolkanicSource::Base64(encoded),
+            Ok(KnownVolkanicSource::Upload(id)) => VolkanicSource::Upload(id),
+            Err(source) => VolkanicSource::Unknown(source),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -44,3 +261,76 @@ pub struct InstanceRequest {
     #[serde(rename = "type")]
     pub inst_type: InstanceType,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn instance(inst_type: Value, status: Value) -> Value {
+        json!({ "name": "lobby", "type": inst_type, "status": status })
+    }
+
+    #[test]
+    fn decodes_known_instances() {
+        let instance = Instance::decode(instance(
+            json!({ "volkanic": { "source": { "url": "https://example.com/a.zip" } } }),
+            json!({ "creating": 40 }),
+        ));
+
+        assert!(instance.decode_error.is_none());
+        assert!(matches!(instance.status, InstanceStatus::Creating(40)));
+        assert!(matches!(
+            instance.inst_type,
+            InstanceType::Volkanic { source: VolkanicSource::Url(url) } if url == "https://example.com/a.zip"
+        ));
+    }
+
+    #[test]
+    fn unknown_status_is_kept() {
+        let instance = Instance::decode(instance(
+            json!({ "container": { "image": "nginx" } }),
+            json!("paused"),
+        ));
+
+        assert!(instance.decode_error.is_none());
+        assert!(matches!(instance.status, InstanceStatus::Unknown(status) if status == "paused"));
+    }
+
+    #[test]
+    fn unknown_source_is_kept() {
+        let instance = Instance::decode(instance(
+            json!({ "volkanic": { "source": { "git": "https://example.com/repo.git" } } }),
+            json!("running"),
+        ));
+
+        assert!(instance.decode_error.is_none());
+        assert!(matches!(
+            instance.inst_type,
+            InstanceType::Volkanic { source: VolkanicSource::Unknown(source) } if source == "git"
+        ));
+    }
+
+    #[test]
+    fn malformed_known_type_is_salvaged() {
+        let raw = instance(json!({ "volkanic": { "source": { "url": 5 } } }), json!("running"));
+        let instance = Instance::decode(raw.clone());
+
+        assert!(instance.decode_error.is_some());
+        assert_eq!(instance.name, "lobby");
+        assert_eq!(instance.raw, raw);
+        assert!(matches!(instance.status, InstanceStatus::Running));
+        assert!(matches!(instance.inst_type, InstanceType::Unknown(kind) if kind == "volkanic"));
+    }
+
+    #[test]
+    fn volume_mounts_accept_read_only_alias() {
+        let camel: VolumeMount =
+            serde_json::from_value(json!({ "source": "data", "target": "/data", "readOnly": true })).unwrap();
+        let snake: VolumeMount =
+            serde_json::from_value(json!({ "source": "data", "target": "/data", "read_only": true })).unwrap();
+
+        assert!(camel.read_only);
+        assert!(snake.read_only);
+    }
+}
@@ -1,35 +1,104 @@
-use reqwest::StatusCode;
+use reqwest::{Client, Method, RequestBuilder, Response, StatusCode};
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
-use std::{collections::HashMap, sync::Arc};
-use tokio::{sync::{broadcast, Mutex}, time};
-use tracing::{debug, info, error};
-
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    future::Future,
+    sync::{atomic::AtomicU64, Arc},
+    time::{Duration, Instant},
+};
+use tokio::{sync::{broadcast, watch, Mutex, Notify}, task::JoinHandle, time};
+use tokio_util::sync::CancellationToken;
+use tracing::{debug, info, error, warn};
+
+pub mod auth;
+pub mod backoff;
+pub mod change;
+pub mod connection;
+pub mod console;
 pub mod event;
+pub mod health;
 pub mod instance;
 mod http;
-
-use instance::{Instance, InstanceRequest};
+pub mod protocol;
+mod sse;
+pub mod stats;
+pub mod tls;
+mod transition;
+pub mod upload;
+pub mod validate;
+
+use auth::{check_authorized, login, RunnerAuth};
+use backoff::{Backoff, BackoffPolicy};
+use change::RunnerChange;
+use connection::Connection;
+use console::{Console, ConsoleLine};
+use health::RunnerHealth;
+use instance::{Instance, InstanceRequest, InstanceStatus};
+use protocol::{Capability, Compatibility, Protocol};
+use stats::StatsSample;
+use tls::TlsSettings;
+use transition::Transition;
+use validate::validate_request;
 
 pub use http::is_valid_url;
-use http::new_client;
+use http::{check_status, new_client, REQUEST_TIMEOUT_MS};
 
 const HEARTBEAT_INTERVAL_MS: u32 = 4000;
-const HEARTBEAT_INTERVAL_OFFLINE_MS: u32 = 12000;
-const SUPPORTED_PROTOCOL: u64 = 1;
+/// Time background tasks have to finish once the runner shuts down
+const SHUTDOWN_TIMEOUT_MS: u64 = 2000;
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct RunnerConDetails {
     pub name: String,
     pub url: String,
+    /// Never written to the config file, kept in the secret store instead.
+    /// Still read so credentials of older config files can be migrated.
+    #[serde(default, skip_serializing)]
+    pub auth: RunnerAuth,
+    /// ID of the credentials in the secret store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret_id: Option<String>,
+    #[serde(default)]
+    pub tls: TlsSettings,
+    #[serde(default)]
+    pub backoff: BackoffPolicy,
 }
 
 pub struct Runner {
     details: Mutex<RunnerConDetails>,
-    update: broadcast::Sender<()>,
-    status_tx: broadcast::Sender<bool>,
-    connected: Mutex<bool>,
+    update: broadcast::Sender<RunnerChange>,
+    /// Whether the runner is connected, mirrors the heartbeat
+    status_tx: watch::Sender<bool>,
+    connection: Mutex<Connection>,
     instances: Mutex<HashMap<String, Instance>>,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+    /// Cancelled once the runner shuts down, stopping all its tasks
+    cancel: CancellationToken,
+    /// Session token obtained by logging in, if the runner requires it
+    session: Mutex<Option<String>>,
+    /// Client shared by all requests, rebuilt when the details change
+    client: Mutex<Option<Client>>,
+    /// Instance status changes awaiting confirmation by the runner
+    pending: Mutex<HashMap<String, Transition>>,
+    transition_seq: AtomicU64,
+    consoles: Mutex<HashMap<String, Console>>,
+    console_tx: broadcast::Sender<(String, ConsoleLine)>,
+    /// Commands sent to each instance
+    input_history: Mutex<HashMap<String, VecDeque<String>>>,
+    /// Recorded resource usage of each instance
+    stats: Mutex<HashMap<String, VecDeque<StatsSample>>>,
+    stats_tx: broadcast::Sender<(String, StatsSample)>,
+    /// Tasks pulling the resource usage of watched instances
+    stats_watchers: Mutex<HashMap<String, JoinHandle<()>>>,
+    /// Last health report, refreshed with the heartbeat
+    health: Mutex<Option<RunnerHealth>>,
+    /// When the last health update was sent to listeners
+    health_sent: Mutex<Option<Instant>>,
+    /// Wakes all loops waiting to reconnect
+    reconnect: Notify,
+    /// Protocol version and features, negotiated on every heartbeat
+    protocol: Mutex<Protocol>,
 }
 
 #[derive(Debug, thiserror::Error)]
@@ -40,11 +109,34 @@ pub enum Error {
     ResponseDecode,
     #[error("Not a Volkanic Runner")]
     NotVolkanicRunner,
-    #[error("Protocol version mismatch (found {0}, expected {1})")]
-    /// Order: (local, remote)
-    ProtocolMismatch(u64, u64),
-    #[error("Broadcast receiver error")]
-    BroadcastReceiver(broadcast::error::RecvError),
+    #[error("The runner is newer than this console, update the console to use it")]
+    RunnerNewer,
+    #[error("The runner is older than this console, update the runner to use it")]
+    RunnerOlder,
+    #[error("Unsupported by this runner: {0}")]
+    Unsupported(Capability),
+    #[error("Unsupported by this runner: {0} instances")]
+    UnsupportedInstanceType(String),
+    #[error("Unauthorized, check the runner credentials")]
+    Unauthorized,
+    #[error("TLS configuration error: {0}")]
+    Tls(String),
+    #[error("Not found: {0}")]
+    NotFound(String),
+    #[error("Conflict: {0}")]
+    Conflict(String),
+    #[error("Invalid state: {0}")]
+    InvalidState(String),
+    #[error("Runner error: {0}")]
+    Server(String),
+    #[error("Unexpected response ({0}): {1}")]
+    UnexpectedStatus(u16, String),
+    #[error("File error: {0}")]
+    File(String),
+    #[error("Checksum mismatch, the upload was corrupted")]
+    ChecksumMismatch,
+    #[error("Invalid instance: {0}")]
+    Validation(String),
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -52,20 +144,32 @@ pub struct RunnerInfo {
     pub version: String,
     pub protocol: u64,
     pub mode: RunnerMode,
+    /// Negotiated locally, not sent by the runner
+    #[serde(skip)]
+    pub compatibility: Compatibility,
+    #[serde(skip)]
+    pub capabilities: HashSet<Capability>,
+    #[serde(skip)]
+    pub instance_types: HashSet<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub enum RunnerMode {
     #[serde(rename = "no-auth")]
     NoAuth,
+    #[serde(rename = "token")]
+    Token,
+    #[serde(rename = "login")]
+    Login,
 }
 
 impl Runner {
-    pub async fn info<U: std::fmt::Display>(url: U) -> Result<RunnerInfo, Error> {
-        let client = new_client().map_err(Error::Http)?;
+    pub async fn info<U: std::fmt::Display>(url: U, tls: &TlsSettings) -> Result<RunnerInfo, Error> {
+        let client = new_client(tls)?;
 
         let info_raw = client
             .get(format!("{}/info", url))
+            .timeout(Duration::from_millis(REQUEST_TIMEOUT_MS))
             .send()
             .await
             .map_err(Error::Http)?
@@ -77,57 +181,190 @@ impl Runner {
 
         info!("Got remote info: {}", &info_raw);
 
-        let protocol = match info_value["protocol"].as_u64() {
-            Some(p) => p,
-            None => {
+        let protocol = match Protocol::from_info(&info_value) {
+            Ok(o) => o,
+            Err(e) => {
                 error!("Remote doesn't specify valid protocol");
-                return Err(Error::NotVolkanicRunner);
+                return Err(e);
             }
         };
 
-        if protocol == SUPPORTED_PROTOCOL {
-            info!("Remote protocol matched");
-        } else {
-            error!(
-                "Remote protocol mismatch (expected: {}, found: {})",
-                SUPPORTED_PROTOCOL, protocol
-            );
-            return Err(Error::ProtocolMismatch(SUPPORTED_PROTOCOL, protocol));
+        match protocol.compatibility {
+            Compatibility::Compatible(version) => info!("Negotiated protocol version {}", version),
+            Compatibility::RunnerNewer => warn!("Remote is newer than this console"),
+            Compatibility::RunnerOlder => warn!("Remote is older than this console"),
+            Compatibility::Unknown => {}
         }
 
-        let info =
+        let mut info =
             serde_json::from_str::<RunnerInfo>(&info_raw).map_err(|_| Error::ResponseDecode)?;
+        info.compatibility = protocol.compatibility;
+        info.capabilities = protocol.capabilities;
+        info.instance_types = protocol.instance_types;
 
         Ok(info)
     }
+    /// Adds a runner after checking it's reachable and accepts the
+    /// credentials. Incompatible runners are added as well, their
+    /// features stay unavailable until either side is updated.
     pub async fn new(con_details: RunnerConDetails) -> Result<Arc<Self>, Error> {
-        Self::info(&con_details.url).await?;
+        let info = Self::info(&con_details.url, &con_details.tls).await?;
+        Self::check_auth(&con_details).await?;
+
+        let protocol = Protocol {
+            compatibility: info.compatibility,
+            capabilities: info.capabilities,
+            instance_types: info.instance_types,
+        };
+
+        Ok(Self::create(con_details, true, protocol).await)
+    }
+    /// Checks if the runner accepts the credentials
+    pub async fn check_auth(con_details: &RunnerConDetails) -> Result<(), Error> {
+        let token = Self::authenticate(con_details).await?;
+        let client = new_client(&con_details.tls)?;
 
+        let mut request = client
+            .get(format!("{}/check", con_details.url))
+            .timeout(Duration::from_millis(REQUEST_TIMEOUT_MS));
+
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+
+        let r = request
+            .send()
+            .await
+            .map_err(Error::Http)?;
+
+        check_authorized(r)?;
+
+        Ok(())
+    }
+    /// Restores a previously saved runner without contacting it first.
+    ///
+    /// The runner starts out disconnected, the heartbeat will connect it
+    /// once it becomes reachable.
+    pub async fn restore(con_details: RunnerConDetails) -> Arc<Self> {
+        Self::create(con_details, false, Protocol::default()).await
+    }
+    async fn create(con_details: RunnerConDetails, connected: bool, protocol: Protocol) -> Arc<Self> {
         let runner = Arc::new(Self {
             details: Mutex::new(con_details),
             // Only the sender is necessary since the receiver can be obtained
             // by calling the `subscribe()` method.
             update: broadcast::channel(255).0,
-            status_tx: broadcast::channel(255).0,
-            connected: Mutex::new(true),
+            status_tx: watch::channel(connected).0,
+            connection: Mutex::new(Connection::new(connected)),
             instances: Mutex::new(HashMap::new()),
+            tasks: Mutex::new(Vec::new()),
+            cancel: CancellationToken::new(),
+            session: Mutex::new(None),
+            client: Mutex::new(None),
+            pending: Mutex::new(HashMap::new()),
+            transition_seq: AtomicU64::new(0),
+            consoles: Mutex::new(HashMap::new()),
+            console_tx: broadcast::channel(1024).0,
+            input_history: Mutex::new(HashMap::new()),
+            stats: Mutex::new(HashMap::new()),
+            stats_tx: broadcast::channel(255).0,
+            stats_watchers: Mutex::new(HashMap::new()),
+            health: Mutex::new(None),
+            health_sent: Mutex::new(None),
+            reconnect: Notify::new(),
+            protocol: Mutex::new(protocol),
         });
 
         Self::start_bg(runner.clone()).await;
 
-        Ok(runner)
+        runner
+    }
+    /// Spawns a task that stops once the runner shuts down
+    pub fn spawn_cancellable<F>(&self, task: F) -> JoinHandle<()>
+    where
+        F: Future<Output = ()> + Send + 'static,
+    {
+        let cancel = self.cancel.clone();
+
+        tokio::spawn(async move {
+            tokio::select! {
+                _ = cancel.cancelled() => {}
+                _ = task => {}
+            }
+        })
     }
-    pub async fn wait_for_update(&self) -> Result<(), Error> {
-        let mut rx = self.update.subscribe();
+    /// Ties a task to the lifetime of the runner, it is awaited on
+    /// shutdown. The task should be spawned with `spawn_cancellable`.
+    pub async fn attach_task(&self, task: JoinHandle<()>) {
+        let mut tasks = self.tasks.lock().await;
+
+        // Short-lived tasks would otherwise pile up until shutdown
+        tasks.retain(|t| !t.is_finished());
+        tasks.push(task);
+    }
+    /// Stops all background tasks belonging to the runner and waits for
+    /// them to finish. Tasks that don't finish in time are aborted.
+    pub async fn shutdown(&self) {
+        self.cancel.cancel();
 
-        rx.recv().await.map_err(Error::BroadcastReceiver)?;
+        self.close_consoles().await;
+        self.unwatch_all_stats().await;
 
-        Ok(())
+        let tasks: Vec<_> = self.tasks.lock().await.drain(..).collect();
+
+        for task in tasks {
+            let abort = task.abort_handle();
+
+            match time::timeout(Duration::from_millis(SHUTDOWN_TIMEOUT_MS), task).await {
+                Ok(Err(e)) if e.is_panic() => error!("Runner task panicked: {}", e),
+                Ok(_) => {}
+                Err(_) => {
+                    warn!("Runner task didn't stop in time, aborting");
+                    abort.abort();
+                }
+            }
+        }
+
+        debug!("Stopped runner tasks");
+    }
+    /// Returns a copy of the connection details
+    pub async fn get_details(&self) -> RunnerConDetails {
+        self.details.lock().await.clone()
     }
-    pub async fn wait_for_status(&self) -> bool {
-        let mut rx = self.status_tx.subscribe();
+    /// Replaces the connection details. If the URL changed, the runner
+    /// is disconnected and reconnects to the new URL.
+    pub async fn set_details(&self, con_details: RunnerConDetails) {
+        let url_changed = {
+            let mut details = self.details.lock().await;
+            let url_changed = details.url != con_details.url;
+
+            *details = con_details;
+
+            url_changed
+        };
+
+        // Credentials and TLS settings may have changed, the session and
+        // client are renewed on the next request
+        *self.session.lock().await = None;
+        *self.client.lock().await = None;
+
+        if url_changed {
+            info!("Runner URL changed, reconnecting");
+
+            self.instances.lock().await.clear();
+            self.stats.lock().await.clear();
+            *self.health.lock().await = None;
+            *self.protocol.lock().await = Protocol::default();
+            *self.connection.lock().await = Connection::new(false);
+            self.send_status(false);
+        }
 
-        rx.recv().await.unwrap()
+        self.send_change(RunnerChange::Details);
+    }
+    /// Returns a receiver for the connection status. Unlike a broadcast
+    /// receiver it always holds the current value and can't lag behind.
+    pub fn subscribe_status(&self) -> watch::Receiver<bool> {
+        self.status_tx.subscribe()
     }
     pub async fn get_name(&self) -> String {
         self.details.lock().await.name.to_string()
@@ -135,8 +372,9 @@ impl Runner {
     pub async fn get_url(&self) -> String {
         self.details.lock().await.url.to_string()
     }
-    pub async fn is_connected(&self) -> bool {
-        *self.connected.lock().await
+    /// Returns the connection quality measured by the heartbeat
+    pub async fn get_connection(&self) -> Connection {
+        self.connection.lock().await.clone()
     }
     /// Returns the local instances. Does not pull the latest data
     /// from the runner.
@@ -150,126 +388,243 @@ impl Runner {
         Ok(())
     }
     pub async fn update_instances(&self) -> Result<(), Error> {
-        let client = new_client().map_err(Error::Http)?;
+        let request = self.request(Method::GET, "/instance/list").await?;
 
         debug!("Client requested, grabbing instances...");
 
-        let instances_raw = client
-            .get(format!("{}/instance/list", self.details.lock().await.url))
-            .send()
-            .await
-            .map_err(Error::Http)?
+        let r = self.send(request).await?;
+
+        let instances_raw = check_status(r)
+            .await?
             .text()
             .await
             .map_err(Error::Http)?;
 
         debug!("Got instance list: {}", &instances_raw);
 
-        let instances = serde_json::from_str(&instances_raw).map_err(|_| Error::ResponseDecode)?;
+        // Instances are decoded one by one, so one the console doesn't
+        // understand doesn't hide the others
+        let instances: HashMap<String, Instance> =
+            serde_json::from_str::<HashMap<String, Value>>(&instances_raw)
+                .map_err(|_| Error::ResponseDecode)?
+                .into_iter()
+                .map(|(id, value)| (id, Instance::decode(value)))
+                .collect();
 
         debug!("Decoded instance list");
 
         *self.instances.lock().await = instances;
+        // The runner state is authoritative, nothing is pending anymore
+        self.pending.lock().await.clear();
 
-        let _ = self.update.send(());
+        self.send_change(RunnerChange::Instances);
 
         Ok(())
     }
-    pub async fn del_instance(&self, id: String) -> Result<(), Error> {
-        let client = new_client().map_err(Error::Http)?;
+    pub async fn del_instance(self: &Arc<Self>, id: String) -> Result<(), Error> {
+        self.instance_action(id, "delete", InstanceStatus::Deleting).await
+    }
+    pub async fn new_instance(&self, instance: InstanceRequest) -> Result<(), Error> {
+        self.require_instance_type(&instance.inst_type).await?;
+        validate_request(&instance, None).await.into_result()?;
 
-        client
-            .post(format!("{}/instance/{}/delete", self.details.lock().await.url, id))
-            .send()
-            .await
-            .map_err(Error::Http)?;
+        let request = self.request(Method::POST, "/instance/new").await?;
+
+        check_status(self.send(request.json(&instance)).await?).await?;
 
         Ok(())
     }
-    pub async fn new_instance(&self, instance: InstanceRequest) -> Result<(), Error> {
-        let client = new_client().map_err(Error::Http)?;
+    pub async fn start_instance(self: &Arc<Self>, id: String) -> Result<(), Error> {
+        self.instance_action(id, "start", InstanceStatus::Starting).await
+    }
+    pub async fn stop_instance(self: &Arc<Self>, id: String) -> Result<(), Error> {
+        self.instance_action(id, "stop", InstanceStatus::Stopping).await
+    }
+    /// Sends an instance action, showing the expected status until the
+    /// runner confirms it
+    async fn instance_action(
+        self: &Arc<Self>,
+        id: String,
+        action: &str,
+        status: InstanceStatus,
+    ) -> Result<(), Error> {
+        self.require(Capability::Instances).await?;
+
+        let seq = self.begin_transition(&id, status).await;
+
+        let r = self.post_instance_action(&id, action).await;
+
+        if let Some(seq) = seq {
+            match &r {
+                Ok(_) => self.reconcile_transition(id, seq).await,
+                Err(_) => self.rollback_transition(&id, seq).await,
+            }
+        }
 
-        client
-            .post(format!("{}/instance/new", self.details.lock().await.url))
-            .json(&instance)
-            .send()
-            .await
-            .map_err(Error::Http)?;
+        r
+    }
+    async fn post_instance_action(&self, id: &str, action: &str) -> Result<(), Error> {
+        let request = self.request(Method::POST, &format!("/instance/{}/{}", id, action)).await?;
+
+        check_status(self.send(request).await?).await?;
 
         Ok(())
     }
-    pub async fn start_instance(&self, id: String) -> Result<(), Error> {
-        let client = new_client().map_err(Error::Http)?;
+    /// Builds an authenticated request to the runner with the request timeout
+    pub(crate) async fn request(&self, method: Method, path: &str) -> Result<RequestBuilder, Error> {
+        Ok(self
+            .stream_request(method, path)
+            .await?
+            .timeout(Duration::from_millis(REQUEST_TIMEOUT_MS)))
+    }
+    /// Builds an authenticated request to the runner without a total
+    /// timeout, for long-lived responses
+    pub(crate) async fn stream_request(&self, method: Method, path: &str) -> Result<RequestBuilder, Error> {
+        let client = self.client().await?;
+        let token = self.token().await?;
 
-        client
-            .post(format!("{}/instance/{}/start", self.details.lock().await.url, id))
-            .send()
-            .await
-            .map_err(Error::Http)?;
+        let mut request = client.request(method, format!("{}{}", self.get_url().await, path));
 
-        Ok(())
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+
+        Ok(request)
     }
-    pub async fn stop_instance(&self, id: String) -> Result<(), Error> {
-        let client = new_client().map_err(Error::Http)?;
+    /// Returns the shared client, building it if necessary
+    async fn client(&self) -> Result<Client, Error> {
+        let mut client = self.client.lock().await;
 
-        client
-            .post(format!("{}/instance/{}/stop", self.details.lock().await.url, id))
-            .send()
-            .await
-            .map_err(Error::Http)?;
+        if let Some(client) = client.as_ref() {
+            // Clones share the same connection pool
+            return Ok(client.clone());
+        }
 
-        Ok(())
+        debug!("Building client for runner");
+
+        let new = new_client(&self.details.lock().await.tls)?;
+        *client = Some(new.clone());
+
+        Ok(new)
+    }
+    /// Sends a request, dropping the session if the runner rejects it
+    pub(crate) async fn send(&self, request: RequestBuilder) -> Result<Response, Error> {
+        let r = request.send().await.map_err(Error::Http)?;
+
+        match check_authorized(r) {
+            Ok(r) => Ok(r),
+            Err(e) => {
+                warn!("Runner rejected credentials");
+                *self.session.lock().await = None;
+                Err(e)
+            }
+        }
     }
-    fn send_update(&self) {
-        let _ = self.update.send(());
+    async fn token(&self) -> Result<Option<String>, Error> {
+        let details = self.details.lock().await.clone();
+
+        if let RunnerAuth::Login { .. } = details.auth {
+            let mut session = self.session.lock().await;
+
+            if session.is_none() {
+                debug!("Logging in to runner");
+                *session = Self::authenticate(&details).await?;
+            }
+
+            return Ok(session.clone());
+        }
+
+        Self::authenticate(&details).await
+    }
+    /// Resolves the credentials into a bearer token
+    async fn authenticate(con_details: &RunnerConDetails) -> Result<Option<String>, Error> {
+        match &con_details.auth {
+            RunnerAuth::None => Ok(None),
+            RunnerAuth::Token { token } => Ok(Some(token.clone())),
+            RunnerAuth::Login { username, password } => {
+                let token = login(&con_details.url, &con_details.tls, username, password).await?;
+
+                Ok(Some(token))
+            }
+        }
     }
     fn send_status(&self, status: bool) {
-        let _ = self.status_tx.send(status);
+        // Unlike `send`, this also stores the value if nobody is subscribed
+        self.status_tx.send_replace(status);
     }
-    async fn heartbeat(&self) -> bool {
-        let client = match new_client() {
+    /// Checks if the runner is reachable, returning the round-trip time
+    async fn heartbeat(&self) -> Option<Duration> {
+        let request = match self.request(Method::GET, "/check").await {
             Ok(o) => o,
-            Err(_) => return false,
+            Err(e) => {
+                debug!("Heartbeat failed: {}", e);
+                return None;
+            }
         };
 
-        let r = client
-            .get(format!("{}/check", self.get_url().await))
-            .send()
-            .await;
+        let start = Instant::now();
+        let r = self.send(request).await;
 
         match r {
-            Ok(r) => r.status() == StatusCode::OK,
-            Err(_) => false,
+            Ok(r) if r.status() == StatusCode::OK => Some(start.elapsed()),
+            _ => None,
         }
     }
     /// Starts background tasks for runner
     async fn start_bg(runner: Arc<Self>) {
-        let runner = runner.clone();
+        let event_task = event::event_listen(runner.clone());
+        runner.attach_task(event_task).await;
 
-        event::event_listen(runner.clone()).await;
+        let heartbeat_runner = runner.clone();
+        let heartbeat_task = runner.spawn_cancellable(async move {
+            let runner = heartbeat_runner;
+            let mut backoff = Backoff::new();
 
-        tokio::spawn(async move {
             loop {
-                let connected = runner.heartbeat().await;
+                let rtt = runner.heartbeat().await;
 
-                let old_connected = *runner.connected.lock().await;
-                *runner.connected.lock().await = connected;
+                let (send, old_connected, connected) = {
+                    let mut connection = runner.connection.lock().await;
+                    let old_status = connection.status;
+                    let old_connected = connection.is_connected();
+
+                    match rtt {
+                        Some(rtt) => connection.record_success(rtt),
+                        None => connection.record_failure(),
+                    }
+
+                    let status_changed = connection.status != old_status;
+
+                    (connection.should_send(status_changed, Instant::now()), old_connected, connection.is_connected())
+                };
+
+                if send {
+                    runner.send_change(RunnerChange::Connection);
+                }
+
+                // Refreshed before announcing the connection, so listeners
+                // see the negotiated protocol
+                if connected {
+                    if let Err(e) = runner.refresh_health().await {
+                        debug!("Failed to refresh runner health: {}", e);
+                    }
+                } else {
+                    runner.clear_health().await;
+                }
 
                 if connected != old_connected {
-                    // Send update to all listeners
-                    runner.send_update();
                     // Send new connection status to all listeners
                     runner.send_status(connected);
                 }
-    
-                time::sleep(time::Duration::from_millis(
-                    if connected {
-                        HEARTBEAT_INTERVAL_MS as u64
-                    } else {
-                        HEARTBEAT_INTERVAL_OFFLINE_MS as u64
-                    }
-                )).await;
+
+                if rtt.is_some() {
+                    backoff.reset();
+                    time::sleep(time::Duration::from_millis(HEARTBEAT_INTERVAL_MS as u64)).await;
+                } else {
+                    runner.backoff(&mut backoff, None).await;
+                }
             }
         });
+        runner.attach_task(heartbeat_task).await;
     }
 }
@@ -0,0 +1,209 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{collections::HashSet, fmt};
+use tracing::{info, warn};
+
+use super::{change::RunnerChange, instance::InstanceType, Error, Runner};
+
+/// Oldest protocol version this console can talk
+pub const MIN_PROTOCOL: u64 = 1;
+/// Newest protocol version this console can talk
+pub const MAX_PROTOCOL: u64 = 1;
+
+/// Outcome of negotiating a protocol version with a runner
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Compatibility {
+    /// The runner wasn't reached yet
+    #[default]
+    #[serde(rename = "unknown")]
+    Unknown,
+    /// Highest protocol version both sides support
+    #[serde(rename = "compatible")]
+    Compatible(u64),
+    /// The runner only supports protocol versions newer than this console
+    #[serde(rename = "runner-newer")]
+    RunnerNewer,
+    /// The runner only supports protocol versions older than this console
+    #[serde(rename = "runner-older")]
+    RunnerOlder,
+}
+
+/// Feature of a runner the console may use
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Capability {
+    #[serde(rename = "instances")]
+    Instances,
+    #[serde(rename = "events")]
+    Events,
+    #[serde(rename = "console", alias = "logs")]
+    Console,
+    #[serde(rename = "input")]
+    Input,
+    #[serde(rename = "stats")]
+    Stats,
+    #[serde(rename = "backups")]
+    Backups,
+    #[serde(rename = "files")]
+    Files,
+    #[serde(rename = "uploads")]
+    Uploads,
+}
+
+impl fmt::Display for Capability {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Capability::Instances => "instance management",
+            Capability::Events => "live updates",
+            Capability::Console => "console output",
+            Capability::Input => "console input",
+            Capability::Stats => "resource usage",
+            Capability::Backups => "backups",
+            Capability::Files => "file access",
+            Capability::Uploads => "file uploads",
+        })
+    }
+}
+
+/// Protocol version and features negotiated with a runner
+#[derive(Debug, Clone, Default)]
+pub struct Protocol {
+    pub compatibility: Compatibility,
+    pub capabilities: HashSet<Capability>,
+    /// Instance types the runner can create, e.g. `volkanic`
+    pub instance_types: HashSet<String>,
+}
+
+impl Protocol {
+    /// Negotiates the protocol from the runner's `/info` response.
+    ///
+    /// Runners may list their `capabilities` and `instance_types`,
+    /// otherwise the defaults of the negotiated version are assumed.
+    /// Unknown entries are ignored.
+    pub fn from_info(info: &Value) -> Result<Self, Error> {
+        let max = match info["protocol"].as_u64() {
+            Some(p) => p,
+            None => return Err(Error::NotVolkanicRunner),
+        };
+        // Runners predating negotiation only support a single version
+        let min = info["min_protocol"].as_u64().unwrap_or(max).min(max);
+
+        let compatibility = if min > MAX_PROTOCOL {
+            Compatibility::RunnerNewer
+        } else if max < MIN_PROTOCOL {
+            Compatibility::RunnerOlder
+        } else {
+            Compatibility::Compatible(max.min(MAX_PROTOCOL))
+        };
+
+        let Compatibility::Compatible(version) = compatibility else {
+            return Ok(Self { compatibility, ..Default::default() });
+        };
+
+        let capabilities = match info["capabilities"].as_array() {
+            Some(list) => list
+                .iter()
+                .filter_map(|c| serde_json::from_value::<Capability>(c.clone()).ok())
+                .collect(),
+            None => capabilities_for(version),
+        };
+
+        let instance_types = match info["instance_types"].as_array() {
+            Some(list) => list
+                .iter()
+                .filter_map(|t| t.as_str().map(|t| t.to_string()))
+                .collect(),
+            None => instance_types_for(version),
+        };
+
+        Ok(Self { compatibility, capabilities, instance_types })
+    }
+}
+
+/// Features available in a protocol version
+fn capabilities_for(version: u64) -> HashSet<Capability> {
+    match version {
+        1 => HashSet::from([
+            Capability::Instances,
+            Capability::Events,
+            Capability::Console,
+            Capability::Input,
+            Capability::Stats,
+        ]),
+        _ => HashSet::new(),
+    }
+}
+
+/// Instance types available in a protocol version
+fn instance_types_for(version: u64) -> HashSet<String> {
+    match version {
+        1 => HashSet::from([String::from("volkanic")]),
+        _ => HashSet::new(),
+    }
+}
+
+impl Runner {
+    /// Returns the protocol negotiated with the runner
+    pub async fn get_protocol(&self) -> Protocol {
+        self.protocol.lock().await.clone()
+    }
+    /// Fails if the runner can't be used for a feature. If the runner
+    /// wasn't reached yet, the feature is assumed to be available.
+    pub async fn require(&self, capability: Capability) -> Result<(), Error> {
+        let protocol = self.protocol.lock().await;
+
+        match protocol.compatibility {
+            Compatibility::Unknown => Ok(()),
+            Compatibility::RunnerNewer => Err(Error::RunnerNewer),
+            Compatibility::RunnerOlder => Err(Error::RunnerOlder),
+            Compatibility::Compatible(_) => {
+                if protocol.capabilities.contains(&capability) {
+                    Ok(())
+                } else {
+                    Err(Error::Unsupported(capability))
+                }
+            }
+        }
+    }
+    /// Fails if the runner can't create instances of a type
+    pub async fn require_instance_type(&self, inst_type: &InstanceType) -> Result<(), Error> {
+        self.require(Capability::Instances).await?;
+
+        if let InstanceType::Unknown(kind) = inst_type {
+            return Err(Error::UnsupportedInstanceType(kind.clone()));
+        }
+
+        let protocol = self.protocol.lock().await;
+
+        match protocol.compatibility {
+            Compatibility::Compatible(_) if !protocol.instance_types.contains(inst_type.kind()) => {
+                Err(Error::UnsupportedInstanceType(inst_type.kind().to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+    /// Replaces the negotiated protocol, sending an update if the
+    /// compatibility changed
+    pub(super) async fn set_protocol(&self, protocol: Protocol) {
+        let changed = {
+            let mut current = self.protocol.lock().await;
+            let changed = current.compatibility != protocol.compatibility
+                || current.capabilities != protocol.capabilities
+                || current.instance_types != protocol.instance_types;
+
+            *current = protocol;
+
+            changed
+        };
+
+        if changed {
+            match self.protocol.lock().await.compatibility {
+                Compatibility::Compatible(version) => info!("Using protocol version {}", version),
+                Compatibility::RunnerNewer => warn!("Runner is newer than this console"),
+                Compatibility::RunnerOlder => warn!("Runner is older than this console"),
+                Compatibility::Unknown => {}
+            }
+
+            self.send_change(RunnerChange::Details);
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use std::time::Duration;
+
+pub const LAST_EVENT_ID_HEADER: &str = "Last-Event-ID";
+const DEFAULT_EVENT_TYPE: &str = "message";
+const BOM: &str = "\u{feff}";
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SseEvent {
+    /// Event type, `message` unless specified by the stream
+    pub event: String,
+    pub data: String,
+    /// Last event ID at the time the event was dispatched
+    pub id: Option<String>,
+}
+
+/// Incremental Server-Sent Events decoder following the parsing rules of
+/// the HTML Living Standard. Events may be split across or batched within
+/// chunks in any way.
+#[derive(Debug, Default)]
+pub struct SseDecoder {
+    /// Bytes of an incomplete line
+    buf: Vec<u8>,
+    /// Set if the last chunk ended with a carriage return, in which case
+    /// a leading line feed in the next chunk belongs to the same line ending.
+    skip_lf: bool,
+    /// Set once the start of the stream has been checked for a BOM
+    started: bool,
+    data: String,
+    event: String,
+    last_event_id: Option<String>,
+    retry: Option<Duration>,
+}
+
+impl SseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Creates a decoder for a reconnection, keeping the last event ID
+    /// and reconnection time of the previous stream.
+    pub fn resume(&self) -> Self {
+        Self {
+            last_event_id: self.last_event_id.clone(),
+            retry: self.retry,
+            ..Self::default()
+        }
+    }
+    /// Last event ID received, should be sent as `Last-Event-ID` on reconnect
+    pub fn last_event_id(&self) -> Option<&str> {
+        self.last_event_id.as_deref()
+    }
+    /// Reconnection time requested by the server
+    pub fn retry(&self) -> Option<Duration> {
+        self.retry
+    }
+    /// Feeds a chunk of the stream into the decoder, returning all events
+    /// completed by it.
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        let mut chunk = chunk;
+
+        if self.skip_lf && !chunk.is_empty() {
+            self.skip_lf = false;
+
+            if let Some(rest) = chunk.strip_prefix(b"\n") {
+                chunk = rest;
+            }
+        }
+
+        let mut start = 0;
+        let mut i = 0;
+
+        while i < chunk.len() {
+            match chunk[i] {
+                b'\n' | b'\r' => {
+                    self.buf.extend_from_slice(&chunk[start..i]);
+                    let line = std::mem::take(&mut self.buf);
+
+                    if let Some(event) = self.process_line(&line) {
+                        events.push(event);
+                    }
+
+                    if chunk[i] == b'\r' {
+                        if i + 1 == chunk.len() {
+                            self.skip_lf = true;
+                        } else if chunk[i + 1] == b'\n' {
+                            i += 1;
+                        }
+                    }
+
+                    start = i + 1;
+                }
+                _ => {}
+            }
+
+            i += 1;
+        }
+
+        self.buf.extend_from_slice(&chunk[start..]);
+
+        events
+    }
+    fn process_line(&mut self, line: &[u8]) -> Option<SseEvent> {
+        let line = String::from_utf8_lossy(line);
+        let mut line = line.as_ref();
+
+        if !self.started {
+            self.started = true;
+            line = line.strip_prefix(BOM).unwrap_or(line);
+        }
+
+        if line.is_empty() {
+            return self.dispatch();
+        }
+
+        // Comments, commonly used as keep-alives
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+
+        match field {
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "event" => {
+                self.event = value.to_string();
+            }
+            "id" if !value.contains('\0') => {
+                self.last_event_id = match value.is_empty() {
+                    true => None,
+                    false => Some(value.to_string()),
+                };
+            }
+            "retry" if !value.is_empty() && value.bytes().all(|b| b.is_ascii_digit()) => {
+                if let Ok(ms) = value.parse::<u64>() {
+                    self.retry = Some(Duration::from_millis(ms));
+                }
+            }
+            // Unknown fields are ignored
+            _ => {}
+        }
+
+        None
+    }
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        let event = std::mem::take(&mut self.event);
+
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let mut data = std::mem::take(&mut self.data);
+        data.pop();
+
+        Some(SseEvent {
+            event: match event.is_empty() {
+                true => DEFAULT_EVENT_TYPE.to_string(),
+                false => event,
+            },
+            data,
+            id: self.last_event_id.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feed_all(decoder: &mut SseDecoder, chunks: &[&[u8]]) -> Vec<SseEvent> {
+        chunks.iter().flat_map(|c| decoder.feed(c)).collect()
+    }
+
+    fn message(data: &str, id: Option<&str>) -> SseEvent {
+        SseEvent {
+            event: DEFAULT_EVENT_TYPE.to_string(),
+            data: data.to_string(),
+            id: id.map(|id| id.to_string()),
+        }
+    }
+
+    #[test]
+    fn split_mid_line() {
+        let mut decoder = SseDecoder::new();
+        let events = feed_all(&mut decoder, &[b"da", b"ta: hel", b"lo\n", b"\n"]);
+
+        assert_eq!(events, vec![message("hello", None)]);
+    }
+
+    #[test]
+    fn split_mid_crlf() {
+        let mut decoder = SseDecoder::new();
+        let events = feed_all(&mut decoder, &[b"data: a\r", b"\n\r", b"\ndata: b\r\n\r\n"]);
+
+        assert_eq!(events, vec![message("a", None), message("b", None)]);
+    }
+
+    #[test]
+    fn lone_cr_ends_lines() {
+        let mut decoder = SseDecoder::new();
+        let events = feed_all(&mut decoder, &[b"data: a\r\rdata: b\r", b"\r"]);
+
+        assert_eq!(events, vec![message("a", None), message("b", None)]);
+    }
+
+    #[test]
+    fn multi_line_data() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.feed(b"event: update\ndata: one\ndata:two\ndata\n\n");
+
+        assert_eq!(events, vec![SseEvent {
+            event: "update".to_string(),
+            data: "one\ntwo\n".to_string(),
+            id: None,
+        }]);
+    }
+
+    #[test]
+    fn comments_are_ignored() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.feed(b": keep-alive\n\n:\ndata: a\n: between\n\n");
+
+        assert_eq!(events, vec![message("a", None)]);
+    }
+
+    #[test]
+    fn bom_is_stripped_once() {
+        let mut decoder = SseDecoder::new();
+        let events = decoder.feed("\u{feff}data: a\n\n".as_bytes());
+
+        assert_eq!(events, vec![message("a", None)]);
+    }
+
+    #[test]
+    fn id_handling() {
+        let mut decoder = SseDecoder::new();
+
+        let events = decoder.feed(b"id: 1\ndata: a\n\n");
+        assert_eq!(events, vec![message("a", Some("1"))]);
+
+        // IDs containing NUL are ignored
+        let events = decoder.feed(b"id: 2\0\ndata: b\n\n");
+        assert_eq!(events, vec![message("b", Some("1"))]);
+
+        // IDs persist across events and apply without data
+        decoder.feed(b"id: 3\n\n");
+        assert_eq!(decoder.last_event_id(), Some("3"));
+
+        // An empty ID resets it
+        let events = decoder.feed(b"id\ndata: c\n\n");
+        assert_eq!(events, vec![message("c", None)]);
+        assert_eq!(decoder.last_event_id(), None);
+    }
+
+    #[test]
+    fn retry_handling() {
+        let mut decoder = SseDecoder::new();
+
+        decoder.feed(b"retry: 2500\n\n");
+        assert_eq!(decoder.retry(), Some(Duration::from_millis(2500)));
+
+        // Values that aren't only digits are ignored
+        decoder.feed(b"retry: 10s\nretry: -1\nretry:\n\n");
+        assert_eq!(decoder.retry(), Some(Duration::from_millis(2500)));
+    }
+
+    #[test]
+    fn resume_keeps_id_and_retry() {
+        let mut decoder = SseDecoder::new();
+        decoder.feed(b"id: 7\nretry: 1000\ndata: a\n\ndata: incomplete");
+
+        let mut resumed = decoder.resume();
+
+        assert_eq!(resumed.last_event_id(), Some("7"));
+        assert_eq!(resumed.retry(), Some(Duration::from_millis(1000)));
+
+        // The incomplete event of the previous stream is dropped
+        let events = resumed.feed(b"data: b\n\n");
+        assert_eq!(events, vec![message("b", Some("7"))]);
+    }
+}
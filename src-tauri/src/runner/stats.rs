@@ -0,0 +1,156 @@
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+use tokio::{sync::broadcast, task::JoinHandle, time};
+use tracing::debug;
+
+use super::{http::check_status, protocol::Capability, Error, Runner};
+
+/// Samples kept per instance
+const STATS_HISTORY_LEN: usize = 300;
+/// Interval stats of watched instances are pulled in, in milliseconds
+const STATS_POLL_INTERVAL_MS: u64 = 5000;
+
+/// Resource usage of an instance as reported by the runner
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InstanceStats {
+    /// CPU usage in percent of a single core
+    pub cpu: f64,
+    /// Memory usage in bytes
+    pub memory: u64,
+    #[serde(default)]
+    pub memory_limit: Option<u64>,
+    /// Uptime in seconds
+    pub uptime: u64,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct StatsSample {
+    /// Unix timestamp in milliseconds of when the sample was received
+    pub time: u64,
+    pub stats: InstanceStats,
+}
+
+impl Runner {
+    /// Pulls the current resource usage of an instance from the runner
+    pub async fn fetch_stats(&self, id: &str) -> Result<InstanceStats, Error> {
+        self.require(Capability::Stats).await?;
+
+        let request = self.request(Method::GET, &format!("/instance/{}/stats", id)).await?;
+
+        let stats = check_status(self.send(request).await?)
+            .await?
+            .json::<InstanceStats>()
+            .await
+            .map_err(|_| Error::ResponseDecode)?;
+
+        self.push_stats(id, stats.clone()).await;
+
+        Ok(stats)
+    }
+    /// Returns the recorded resource usage of an instance, oldest first.
+    /// Pulls the current usage first if nothing was recorded yet.
+    pub async fn get_stats(&self, id: &str) -> Result<Vec<StatsSample>, Error> {
+        let recorded = self.stats.lock().await.contains_key(id);
+
+        if !recorded {
+            self.fetch_stats(id).await?;
+        }
+
+        match self.stats.lock().await.get(id) {
+            Some(samples) => Ok(samples.iter().cloned().collect()),
+            None => Ok(Vec::new()),
+        }
+    }
+    /// Starts pulling the resource usage of an instance on an interval,
+    /// for runners that don't push it with events. Returns the samples
+    /// recorded so far.
+    pub async fn watch_stats(self: &Arc<Self>, id: &str) -> Result<Vec<StatsSample>, Error> {
+        let samples = self.get_stats(id).await?;
+
+        let mut watchers = self.stats_watchers.lock().await;
+
+        let watching = matches!(watchers.get(id), Some(task) if !task.is_finished());
+
+        if !watching {
+            watchers.insert(id.to_string(), self.poll_stats(id.to_string()));
+        }
+
+        Ok(samples)
+    }
+    /// Stops pulling the resource usage of an instance
+    pub async fn unwatch_stats(&self, id: &str) {
+        if let Some(task) = self.stats_watchers.lock().await.remove(id) {
+            task.abort();
+
+            debug!("Stopped watching stats of instance {}", id);
+        }
+    }
+    /// Stops pulling the resource usage of all instances
+    pub(super) async fn unwatch_all_stats(&self) {
+        for (_, task) in self.stats_watchers.lock().await.drain() {
+            task.abort();
+        }
+    }
+    /// Returns a receiver for stats samples of all instances, as
+    /// (instance ID, sample)
+    pub fn subscribe_stats(&self) -> broadcast::Receiver<(String, StatsSample)> {
+        self.stats_tx.subscribe()
+    }
+    /// Records a sample and sends it to all listeners
+    pub(super) async fn push_stats(&self, id: &str, stats: InstanceStats) {
+        let sample = StatsSample { time: now_ms(), stats };
+
+        {
+            let mut all_stats = self.stats.lock().await;
+            let samples = all_stats.entry(id.to_string()).or_default();
+
+            if samples.len() >= STATS_HISTORY_LEN {
+                samples.pop_front();
+            }
+
+            samples.push_back(sample.clone());
+        }
+
+        let _ = self.stats_tx.send((id.to_string(), sample));
+    }
+    fn poll_stats(self: &Arc<Self>, id: String) -> JoinHandle<()> {
+        let runner = self.clone();
+
+        self.spawn_cancellable(async move {
+            loop {
+                time::sleep(Duration::from_millis(STATS_POLL_INTERVAL_MS)).await;
+
+                // Samples pushed with events make pulling unnecessary
+                let recent = runner
+                    .stats
+                    .lock()
+                    .await
+                    .get(&id)
+                    .and_then(|samples| samples.back())
+                    .is_some_and(|sample| now_ms().saturating_sub(sample.time) < STATS_POLL_INTERVAL_MS);
+
+                if recent {
+                    continue;
+                }
+
+                match runner.fetch_stats(&id).await {
+                    Ok(_) => {}
+                    Err(Error::Unsupported(_)) => break,
+                    Err(e) => debug!("Failed to pull stats of instance {}: {}", id, e),
+                }
+            }
+        })
+    }
+}
+
+/// Current Unix timestamp in milliseconds
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or_default()
+}
@@ -0,0 +1,200 @@
+use reqwest::{Certificate, ClientBuilder, Identity};
+use rustls::{
+    client::danger::{HandshakeSignatureValid, ServerCertVerified, ServerCertVerifier},
+    crypto::{ring, verify_tls12_signature, verify_tls13_signature, CryptoProvider},
+    pki_types::{CertificateDer, ServerName, UnixTime},
+    ClientConfig, DigitallySignedStruct, SignatureScheme,
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{fs, path::PathBuf, sync::Arc};
+
+use super::Error;
+
+/// TLS settings for connecting to a runner
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TlsSettings {
+    /// PEM bundle of CA certificates trusted in addition to the built-in ones
+    #[serde(default, alias = "ca_bundle")]
+    pub ca_bundle: Option<PathBuf>,
+    /// SHA-256 fingerprint of the runner certificate in hex, replaces CA
+    /// validation when set
+    #[serde(default, alias = "pinned_fingerprint")]
+    pub pinned_fingerprint: Option<String>,
+    /// PEM client certificate for mutual TLS, may also contain the key
+    #[serde(default, alias = "client_cert")]
+    pub client_cert: Option<PathBuf>,
+    /// PEM private key of the client certificate
+    #[serde(default, alias = "client_key")]
+    pub client_key: Option<PathBuf>,
+    /// Disables certificate validation entirely
+    #[serde(default, alias = "accept_invalid_certs")]
+    pub accept_invalid_certs: bool,
+}
+
+/// Applies the TLS settings to the client builder
+pub fn apply(builder: ClientBuilder, tls: &TlsSettings) -> Result<ClientBuilder, Error> {
+    if *tls == TlsSettings::default() {
+        return Ok(builder);
+    }
+
+    if let Some(fingerprint) = &tls.pinned_fingerprint {
+        return Ok(builder.use_preconfigured_tls(pinned_config(fingerprint, tls)?));
+    }
+
+    let mut builder = builder
+        .use_rustls_tls()
+        .danger_accept_invalid_certs(tls.accept_invalid_certs);
+
+    if let Some(path) = &tls.ca_bundle {
+        let bundle = read(path)?;
+
+        for cert in Certificate::from_pem_bundle(&bundle).map_err(|e| Error::Tls(e.to_string()))? {
+            builder = builder.add_root_certificate(cert);
+        }
+    }
+
+    if let Some(pem) = client_identity_pem(tls)? {
+        let identity = Identity::from_pem(&pem).map_err(|e| Error::Tls(e.to_string()))?;
+
+        builder = builder.identity(identity);
+    }
+
+    Ok(builder)
+}
+
+/// Builds a config only trusting the certificate with the given fingerprint
+fn pinned_config(fingerprint: &str, tls: &TlsSettings) -> Result<ClientConfig, Error> {
+    let provider = Arc::new(ring::default_provider());
+
+    let verifier = PinnedVerifier {
+        fingerprint: parse_fingerprint(fingerprint)?,
+        provider: provider.clone(),
+    };
+
+    let builder = ClientConfig::builder_with_provider(provider)
+        .with_safe_default_protocol_versions()
+        .map_err(|e| Error::Tls(e.to_string()))?
+        .dangerous()
+        .with_custom_certificate_verifier(Arc::new(verifier));
+
+    match client_identity_pem(tls)? {
+        Some(pem) => {
+            let certs = rustls_pemfile::certs(&mut pem.as_slice())
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(|e| Error::Tls(e.to_string()))?;
+            let key = rustls_pemfile::private_key(&mut pem.as_slice())
+                .map_err(|e| Error::Tls(e.to_string()))?
+                .ok_or(Error::Tls(String::from("No private key found for client certificate")))?;
+
+            builder
+                .with_client_auth_cert(certs, key)
+                .map_err(|e| Error::Tls(e.to_string()))
+        }
+        None => Ok(builder.with_no_client_auth()),
+    }
+}
+
+/// Reads the client certificate and key into a single PEM buffer
+fn client_identity_pem(tls: &TlsSettings) -> Result<Option<Vec<u8>>, Error> {
+    let mut pem = match &tls.client_cert {
+        Some(path) => read(path)?,
+        None => return Ok(None),
+    };
+
+    if let Some(path) = &tls.client_key {
+        pem.push(b'\n');
+        pem.extend(read(path)?);
+    }
+
+    Ok(Some(pem))
+}
+
+/// Parses a hex fingerprint, with or without colon separators
+fn parse_fingerprint(fingerprint: &str) -> Result<Vec<u8>, Error> {
+    let hex: String = fingerprint
+        .chars()
+        .filter(|c| *c != ':' && !c.is_whitespace())
+        .collect();
+
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err(Error::Tls(String::from("Fingerprint must be a SHA-256 hash in hex")));
+    }
+
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| Error::Tls(String::from("Fingerprint must be a SHA-256 hash in hex")))
+}
+
+fn read(path: &PathBuf) -> Result<Vec<u8>, Error> {
+    fs::read(path).map_err(|e| Error::Tls(format!("Failed to read {}: {}", path.display(), e)))
+}
+
+#[derive(Debug)]
+struct PinnedVerifier {
+    fingerprint: Vec<u8>,
+    provider: Arc<CryptoProvider>,
+}
+
+impl ServerCertVerifier for PinnedVerifier {
+    fn verify_server_cert(
+        &self,
+        end_entity: &CertificateDer<'_>,
+        _intermediates: &[CertificateDer<'_>],
+        _server_name: &ServerName<'_>,
+        _ocsp_response: &[u8],
+        _now: UnixTime,
+    ) -> Result<ServerCertVerified, rustls::Error> {
+        let digest = Sha256::digest(end_entity.as_ref());
+
+        if digest.as_slice() == self.fingerprint.as_slice() {
+            Ok(ServerCertVerified::assertion())
+        } else {
+            Err(rustls::Error::General(String::from(
+                "Certificate doesn't match pinned fingerprint",
+            )))
+        }
+    }
+    fn verify_tls12_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls12_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+    fn verify_tls13_signature(
+        &self,
+        message: &[u8],
+        cert: &CertificateDer<'_>,
+        dss: &DigitallySignedStruct,
+    ) -> Result<HandshakeSignatureValid, rustls::Error> {
+        verify_tls13_signature(message, cert, dss, &self.provider.signature_verification_algorithms)
+    }
+    fn supported_verify_schemes(&self) -> Vec<SignatureScheme> {
+        self.provider
+            .signature_verification_algorithms
+            .supported_schemes()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn accepts_camel_and_snake_case_keys() {
+        let camel: TlsSettings =
+            serde_json::from_value(json!({ "pinnedFingerprint": "ab", "acceptInvalidCerts": true })).unwrap();
+        let snake: TlsSettings =
+            serde_json::from_value(json!({ "pinned_fingerprint": "ab", "accept_invalid_certs": true })).unwrap();
+
+        assert_eq!(camel, snake);
+        assert_eq!(camel.pinned_fingerprint.as_deref(), Some("ab"));
+        assert_eq!(serde_json::to_value(&camel).unwrap()["pinnedFingerprint"], "ab");
+    }
+}
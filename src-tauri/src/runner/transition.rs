@@ -0,0 +1,92 @@
+use std::sync::{atomic::Ordering, Arc};
+use tokio::time;
+use tracing::{debug, warn};
+
+use super::{instance::InstanceStatus, Runner};
+
+/// Time the runner has to confirm a transition before the instances are
+/// pulled again
+const RECONCILE_TIMEOUT_MS: u64 = 10000;
+
+/// Instance status change applied locally before the runner confirmed it
+#[derive(Debug, Clone)]
+pub struct Transition {
+    seq: u64,
+    previous: InstanceStatus,
+}
+
+impl Runner {
+    /// Applies the status locally until the runner confirms or rejects it.
+    /// Returns `None` if the instance isn't known locally.
+    pub(super) async fn begin_transition(&self, id: &str, status: InstanceStatus) -> Option<u64> {
+        let previous = {
+            let mut instances = self.instances.lock().await;
+
+            let instance = instances.get_mut(id)?;
+
+            std::mem::replace(&mut instance.status, status)
+        };
+
+        let seq = self.transition_seq.fetch_add(1, Ordering::Relaxed);
+
+        self.pending.lock().await.insert(id.to_string(), Transition { seq, previous });
+
+        self.send_instance(id).await;
+
+        Some(seq)
+    }
+    /// Restores the previous status if the transition is still pending
+    pub(super) async fn rollback_transition(&self, id: &str, seq: u64) {
+        let transition = {
+            let mut pending = self.pending.lock().await;
+
+            match pending.get(id) {
+                Some(t) if t.seq == seq => pending.remove(id),
+                _ => None,
+            }
+        };
+
+        let Some(transition) = transition else {
+            return;
+        };
+
+        debug!("Rolling back status of instance {}", id);
+
+        if let Some(instance) = self.instances.lock().await.get_mut(id) {
+            instance.status = transition.previous;
+        }
+
+        self.send_instance(id).await;
+    }
+    /// Marks the pending transition of an instance as confirmed by the runner
+    pub(super) async fn confirm_transition(&self, id: &str) {
+        self.pending.lock().await.remove(id);
+    }
+    /// Pulls the instances from the runner if it doesn't confirm the
+    /// transition in time, rolling back if that fails as well.
+    pub(super) async fn reconcile_transition(self: &Arc<Self>, id: String, seq: u64) {
+        let runner = self.clone();
+
+        let task = self.spawn_cancellable(async move {
+            time::sleep(time::Duration::from_millis(RECONCILE_TIMEOUT_MS)).await;
+
+            let unconfirmed = matches!(
+                runner.pending.lock().await.get(&id),
+                Some(t) if t.seq == seq
+            );
+
+            if !unconfirmed {
+                return;
+            }
+
+            warn!("Runner didn't confirm status of instance {}, refreshing", id);
+
+            if let Err(e) = runner.update_instances().await {
+                warn!("Failed to refresh instances: {}", e);
+                runner.rollback_transition(&id, seq).await;
+            }
+        });
+
+        self.attach_task(task).await;
+    }
+}
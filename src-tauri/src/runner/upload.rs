@@ -0,0 +1,159 @@
+use reqwest::{
+    header::{CONTENT_RANGE, CONTENT_TYPE},
+    Method,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::{path::Path, time::Duration};
+use tokio::{fs::File, io::AsyncReadExt};
+use tracing::{debug, warn};
+
+use super::{
+    http::check_status,
+    instance::{InstanceRequest, InstanceType, VolkanicSource},
+    protocol::Capability,
+    Error,
+    Runner,
+};
+
+/// Size of the chunks files are uploaded in
+const UPLOAD_CHUNK_SIZE: u64 = 1024 * 1024;
+/// Timeout for uploading a single chunk in milliseconds
+const CHUNK_TIMEOUT_MS: u64 = 60000;
+
+/// A file uploaded to the runner
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct Artifact {
+    pub id: String,
+    pub size: u64,
+    /// Hex encoded SHA-256 of the content
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct UploadProgress {
+    /// Bytes sent so far
+    pub sent: u64,
+    pub total: u64,
+}
+
+/// Upload created on the runner, waiting for content
+#[derive(Debug, Clone, Deserialize)]
+struct PendingUpload {
+    id: String,
+}
+
+impl Runner {
+    /// Uploads a file to the runner in chunks, calling `progress` after
+    /// every chunk. The runner verifies the checksum once all chunks
+    /// were sent.
+    pub async fn upload(&self, path: &Path, progress: impl Fn(UploadProgress)) -> Result<Artifact, Error> {
+        self.require(Capability::Uploads).await?;
+
+        let mut file = File::open(path).await.map_err(|e| Error::File(e.to_string()))?;
+        let total = file.metadata().await.map_err(|e| Error::File(e.to_string()))?.len();
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().to_string())
+            .unwrap_or_default();
+
+        let request = self.request(Method::POST, "/upload").await?;
+        let pending = check_status(self.send(request.json(&json!({ "name": name, "size": total }))).await?)
+            .await?
+            .json::<PendingUpload>()
+            .await
+            .map_err(|_| Error::ResponseDecode)?;
+
+        debug!("Uploading {} ({} bytes) as {}", path.display(), total, pending.id);
+
+        let r = self.upload_chunks(&pending.id, &mut file, total, progress).await;
+
+        if r.is_err() {
+            // The runner drops stale uploads eventually, this only frees space sooner
+            if let Err(e) = self.abort_upload(&pending.id).await {
+                warn!("Failed to abort upload {}: {}", pending.id, e);
+            }
+        }
+
+        r
+    }
+    /// Uploads a file and creates a Volkanic instance from it
+    pub async fn new_instance_from_file(
+        &self,
+        name: String,
+        path: &Path,
+        progress: impl Fn(UploadProgress),
+    ) -> Result<(), Error> {
+        let artifact = self.upload(path, progress).await?;
+
+        self.new_instance(InstanceRequest {
+            name,
+            inst_type: InstanceType::Volkanic { source: VolkanicSource::Upload(artifact.id) },
+        })
+        .await
+    }
+    async fn upload_chunks(
+        &self,
+        id: &str,
+        file: &mut File,
+        total: u64,
+        progress: impl Fn(UploadProgress),
+    ) -> Result<Artifact, Error> {
+        let mut hasher = Sha256::new();
+        let mut sent = 0;
+
+        progress(UploadProgress { sent, total });
+
+        while sent < total {
+            let mut chunk = Vec::new();
+            (&mut *file)
+                .take(UPLOAD_CHUNK_SIZE)
+                .read_to_end(&mut chunk)
+                .await
+                .map_err(|e| Error::File(e.to_string()))?;
+
+            if chunk.is_empty() {
+                return Err(Error::File("File changed during upload".to_string()));
+            }
+
+            let len = chunk.len() as u64;
+            hasher.update(&chunk);
+
+            let request = self
+                .stream_request(Method::PUT, &format!("/upload/{}", id))
+                .await?
+                .timeout(Duration::from_millis(CHUNK_TIMEOUT_MS))
+                .header(CONTENT_TYPE, "application/octet-stream")
+                .header(CONTENT_RANGE, format!("bytes {}-{}/{}", sent, sent + len - 1, total))
+                .body(chunk);
+
+            check_status(self.send(request).await?).await?;
+
+            sent += len;
+            progress(UploadProgress { sent, total });
+        }
+
+        let sha256 = format!("{:x}", hasher.finalize());
+
+        let request = self.request(Method::POST, &format!("/upload/{}/complete", id)).await?;
+        let artifact = check_status(self.send(request.json(&json!({ "sha256": sha256 }))).await?)
+            .await?
+            .json::<Artifact>()
+            .await
+            .map_err(|_| Error::ResponseDecode)?;
+
+        if !artifact.sha256.eq_ignore_ascii_case(&sha256) || artifact.size != total {
+            return Err(Error::ChecksumMismatch);
+        }
+
+        Ok(artifact)
+    }
+    async fn abort_upload(&self, id: &str) -> Result<(), Error> {
+        let request = self.request(Method::DELETE, &format!("/upload/{}", id)).await?;
+
+        check_status(self.send(request).await?).await?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{header::CONTENT_TYPE, Client};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+use url::Url;
+
+use super::{
+    http::{new_client, REQUEST_TIMEOUT_MS},
+    instance::{InstanceRequest, InstanceType, VolkanicSource},
+    tls::TlsSettings,
+    Error,
+    Runner,
+};
+
+/// Largest decoded base64 source accepted, larger files should be uploaded
+pub const MAX_BASE64_SIZE: u64 = 16 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Severity {
+    /// The runner will reject the request
+    #[serde(rename = "error")]
+    Error,
+    /// The request may work, but likely won't
+    #[serde(rename = "warning")]
+    Warning,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct ValidationIssue {
+    pub severity: Severity,
+    pub message: String,
+}
+
+/// Archive format detected from the first bytes of a payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub enum SourceFormat {
+    /// Includes JAR files
+    #[serde(rename = "zip")]
+    Zip,
+    #[serde(rename = "gzip")]
+    Gzip,
+    #[serde(rename = "tar")]
+    Tar,
+}
+
+/// Result of checking an instance request before sending it
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct ValidationReport {
+    pub issues: Vec<ValidationIssue>,
+    /// Size of the source in bytes, if known
+    pub size: Option<u64>,
+    pub format: Option<SourceFormat>,
+    /// Whether the source URL was reachable from this console, `None` if
+    /// it wasn't probed
+    pub reachable: Option<bool>,
+}
+
+impl ValidationReport {
+    /// Fails with the first error, if any
+    pub fn into_result(self) -> Result<Self, Error> {
+        match self.issues.iter().find(|i| i.severity == Severity::Error) {
+            Some(issue) => Err(Error::Validation(issue.message.clone())),
+            None => Ok(self),
+        }
+    }
+    fn error(&mut self, message: impl Into<String>) {
+        self.issues.push(ValidationIssue { severity: Severity::Error, message: message.into() });
+    }
+    fn warning(&mut self, message: impl Into<String>) {
+        self.issues.push(ValidationIssue { severity: Severity::Warning, message: message.into() });
+    }
+}
+
+/// Checks an instance request locally. With a runner to `probe` for,
+/// source URLs are requested to check whether they are reachable and how
+/// large they are.
+pub async fn validate_request(request: &InstanceRequest, probe: Option<&Runner>) -> ValidationReport {
+    let mut report = ValidationReport::default();
+
+    if request.name.trim().is_empty() {
+        report.error("The instance name is empty");
+    }
+
+    match &request.inst_type {
+        InstanceType::Volkanic { source } => validate_source(&mut report, source, probe).await,
+        InstanceType::Container { image, .. } => {
+            if image.trim().is_empty() {
+                report.error("The container image is empty");
+            }
+        }
+        InstanceType::Unknown(kind) => report.error(format!("Unknown instance type: {}", kind)),
+    }
+
+    report
+}
+
+async fn validate_source(report: &mut ValidationReport, source: &VolkanicSource, probe: Option<&Runner>) {
+    match source {
+        VolkanicSource::Url(url) => {
+            let url = match Url::parse(url) {
+                Ok(o) => o,
+                Err(e) => {
+                    report.error(format!("The source URL is invalid: {}", e));
+                    return;
+                }
+            };
+
+            if !matches!(url.scheme(), "http" | "https") {
+                report.error(format!("Unsupported URL scheme: {}", url.scheme()));
+                return;
+            }
+
+            if let Some(runner) = probe {
+                probe_url(report, runner, url).await;
+            }
+        }
+        VolkanicSource::Base64(encoded) => {
+            // Checked before decoding to avoid allocating for huge payloads
+            if encoded.len() as u64 / 4 * 3 > MAX_BASE64_SIZE {
+                report.error("The source is too large to send inline, upload it instead");
+                return;
+            }
+
+            let decoded = match STANDARD.decode(encoded.trim()) {
+                Ok(o) => o,
+                Err(e) => {
+                    report.error(format!("The source is not valid base64: {}", e));
+                    return;
+                }
+            };
+
+            if decoded.is_empty() {
+                report.error("The source is empty");
+                return;
+            }
+
+            report.size = Some(decoded.len() as u64);
+            report.format = sniff_format(&decoded);
+
+            if report.format.is_none() {
+                report.warning("The source doesn't look like an archive");
+            }
+        }
+        VolkanicSource::Upload(id) => {
+            if id.trim().is_empty() {
+                report.error("The upload ID is empty");
+            }
+        }
+        VolkanicSource::Unknown(kind) => report.error(format!("Unknown source: {}", kind)),
+    }
+}
+
+/// Sends a HEAD request to the source URL. Failures are only warnings
+/// since the runner may reach URLs this console can't.
+async fn probe_url(report: &mut ValidationReport, runner: &Runner, url: Url) {
+    let client = match runner.probe_client(&url).await {
+        Ok(o) => o,
+        Err(e) => {
+            report.warning(format!("Couldn't check the source URL: {}", e));
+            return;
+        }
+    };
+
+    let request = client.head(url).timeout(Duration::from_millis(REQUEST_TIMEOUT_MS));
+
+    let r = match request.send().await {
+        Ok(o) => o,
+        Err(e) => {
+            report.reachable = Some(false);
+            report.warning(format!("The source URL is unreachable: {}", e));
+            return;
+        }
+    };
+
+    report.reachable = Some(r.status().is_success());
+
+    if !r.status().is_success() {
+        report.warning(format!("The source URL responded with {}", r.status()));
+        return;
+    }
+
+    report.size = r.content_length().filter(|l| *l > 0);
+
+    let content_type = r
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|t| t.to_str().ok())
+        .unwrap_or_default();
+
+    if content_type.starts_with("text/html") {
+        report.warning("The source URL points to a web page, not a file");
+    }
+}
+
+impl Runner {
+    /// Returns a client for requesting a source URL. The runner's TLS
+    /// settings fully apply to its own host, other hosts only trust the
+    /// additional CA bundle since pinning and client certificates are
+    /// specific to the runner.
+    async fn probe_client(&self, url: &Url) -> Result<Client, Error> {
+        let details = self.get_details().await;
+
+        let same_origin = Url::parse(&details.url)
+            .map(|runner| runner.origin() == url.origin())
+            .unwrap_or(false);
+
+        if same_origin {
+            return self.client().await;
+        }
+
+        new_client(&TlsSettings {
+            ca_bundle: details.tls.ca_bundle,
+            ..TlsSettings::default()
+        })
+    }
+}
+
+/// Detects the archive format from magic bytes
+pub fn sniff_format(data: &[u8]) -> Option<SourceFormat> {
+    if data.starts_with(b"PK\x03\x04") {
+        Some(SourceFormat::Zip)
+    } else if data.starts_with(&[0x1f, 0x8b]) {
+        Some(SourceFormat::Gzip)
+    } else if data.len() >= 262 && &data[257..262] == b"ustar" {
+        Some(SourceFormat::Tar)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(source: VolkanicSource) -> InstanceRequest {
+        InstanceRequest {
+            name: String::from("test"),
+            inst_type: InstanceType::Volkanic { source },
+        }
+    }
+
+    fn tar_header() -> Vec<u8> {
+        let mut data = vec![0; 262];
+        data[257..262].copy_from_slice(b"ustar");
+
+        data
+    }
+
+    #[test]
+    fn sniffs_archive_formats() {
+        assert_eq!(sniff_format(b"PK\x03\x04rest"), Some(SourceFormat::Zip));
+        assert_eq!(sniff_format(&[0x1f, 0x8b, 0x08]), Some(SourceFormat::Gzip));
+        assert_eq!(sniff_format(&tar_header()), Some(SourceFormat::Tar));
+        assert_eq!(sniff_format(&tar_header()[..261]), None);
+        assert_eq!(sniff_format(b"#!/bin/sh"), None);
+        assert_eq!(sniff_format(b""), None);
+    }
+
+    #[tokio::test]
+    async fn decodes_base64_sources() {
+        let encoded = STANDARD.encode(b"PK\x03\x04rest");
+        let report = validate_request(&request(VolkanicSource::Base64(encoded)), None).await;
+
+        assert!(report.issues.is_empty());
+        assert_eq!(report.size, Some(8));
+        assert_eq!(report.format, Some(SourceFormat::Zip));
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_base64() {
+        let report = validate_request(&request(VolkanicSource::Base64(String::from("not base64!"))), None).await;
+
+        assert!(report.into_result().is_err());
+    }
+
+    #[tokio::test]
+    async fn warns_about_unknown_formats() {
+        let encoded = STANDARD.encode(b"plain text");
+        let report = validate_request(&request(VolkanicSource::Base64(encoded)), None).await;
+
+        assert_eq!(report.issues.len(), 1);
+        assert_eq!(report.issues[0].severity, Severity::Warning);
+        assert!(report.into_result().is_ok());
+    }
+
+    #[tokio::test]
+    async fn limits_base64_size() {
+        let limit = MAX_BASE64_SIZE as usize / 3 * 4;
+
+        let report = validate_request(&request(VolkanicSource::Base64("A".repeat(limit))), None).await;
+        assert!(report.issues.iter().all(|i| !i.message.contains("too large")));
+
+        let report = validate_request(&request(VolkanicSource::Base64("A".repeat(limit + 8))), None).await;
+        assert!(report.issues.iter().any(|i| i.message.contains("too large")));
+    }
+
+    #[tokio::test]
+    async fn checks_urls() {
+        let report = validate_request(&request(VolkanicSource::Url(String::from("https://example.com/a.zip"))), None).await;
+        assert!(report.issues.is_empty());
+        assert_eq!(report.reachable, None);
+
+        let report = validate_request(&request(VolkanicSource::Url(String::from("not a url"))), None).await;
+        assert!(report.into_result().is_err());
+
+        let report = validate_request(&request(VolkanicSource::Url(String::from("ftp://example.com/a.zip"))), None).await;
+        assert!(report.into_result().is_err());
+    }
+
+    #[test]
+    fn into_result_fails_on_first_error() {
+        let mut report = ValidationReport::default();
+        report.warning("warning");
+        assert!(report.clone().into_result().is_ok());
+
+        report.error("first");
+        report.error("second");
+
+        match report.into_result() {
+            Err(Error::Validation(message)) => assert_eq!(message, "first"),
+            r => panic!("Unexpected result: {:?}", r),
+        }
+    }
+}
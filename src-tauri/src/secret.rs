@@ -0,0 +1,238 @@
+use chacha20poly1305::{
+    aead::{Aead, AeadCore, KeyInit, OsRng},
+    Key, XChaCha20Poly1305, XNonce,
+};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+use tracing::{debug, info, warn};
+
+const KEYRING_SERVICE: &str = "volkanic-console";
+const SECRETS_FILE_NAME: &str = "secrets.bin";
+const KEY_FILE_NAME: &str = "secrets.key";
+const NONCE_LEN: usize = 24;
+
+/// Selects the secret store backend, one of `keyring`, `file` or `memory`.
+/// The platform keyring is used by default, falling back to the encrypted
+/// file if it isn't available.
+pub const SECRET_STORE_VAR: &str = "VK_SECRET_STORE";
+
+#[derive(Debug, thiserror::Error)]
+pub enum Error {
+    #[error("Keyring error: {0}")]
+    Keyring(keyring::Error),
+    #[error("I/O error: {0}")]
+    Io(std::io::Error),
+    #[error("Secret file is corrupted or was encrypted with another key")]
+    Decrypt,
+    #[error("Secret encryption failed")]
+    Encrypt,
+    #[error("JSON error: {0}")]
+    Json(serde_json::Error),
+}
+
+/// Storage for credentials that must not end up in the config file
+pub trait SecretStore: Send + Sync {
+    fn get(&self, id: &str) -> Result<Option<String>, Error>;
+    fn set(&self, id: &str, secret: &str) -> Result<(), Error>;
+    fn delete(&self, id: &str) -> Result<(), Error>;
+}
+
+/// Opens the secret store selected by `VK_SECRET_STORE`
+pub fn open(dir: &Path) -> Result<Box<dyn SecretStore>, Error> {
+    let backend = std::env::var(SECRET_STORE_VAR).unwrap_or_default();
+
+    match backend.as_str() {
+        "memory" => {
+            info!("Using in-memory secret store, secrets will not be saved");
+            Ok(Box::new(MemoryStore::default()))
+        }
+        "file" => {
+            info!("Using encrypted file secret store");
+            Ok(Box::new(FileStore::open(dir)?))
+        }
+        _ => match KeyringStore::probe() {
+            Ok(_) => {
+                debug!("Using platform keyring secret store");
+                Ok(Box::new(KeyringStore))
+            }
+            Err(e) => {
+                warn!("Platform keyring unavailable ({}), using encrypted file instead", e);
+                Ok(Box::new(FileStore::open(dir)?))
+            }
+        },
+    }
+}
+
+/// Stores secrets in the platform keyring (Secret Service, Keychain or
+/// Credential Manager)
+pub struct KeyringStore;
+
+impl KeyringStore {
+    /// Checks if the keyring can be reached
+    fn probe() -> Result<(), Error> {
+        match Self::entry("probe")?.get_password() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::Keyring(e)),
+        }
+    }
+    fn entry(id: &str) -> Result<keyring::Entry, Error> {
+        keyring::Entry::new(KEYRING_SERVICE, id).map_err(Error::Keyring)
+    }
+}
+
+impl SecretStore for KeyringStore {
+    fn get(&self, id: &str) -> Result<Option<String>, Error> {
+        match Self::entry(id)?.get_password() {
+            Ok(o) => Ok(Some(o)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(e) => Err(Error::Keyring(e)),
+        }
+    }
+    fn set(&self, id: &str, secret: &str) -> Result<(), Error> {
+        Self::entry(id)?.set_password(secret).map_err(Error::Keyring)
+    }
+    fn delete(&self, id: &str) -> Result<(), Error> {
+        match Self::entry(id)?.delete_credential() {
+            Ok(_) | Err(keyring::Error::NoEntry) => Ok(()),
+            Err(e) => Err(Error::Keyring(e)),
+        }
+    }
+}
+
+/// Stores secrets in a file encrypted with a key kept next to it.
+///
+/// This only protects against the secrets being read from backups or
+/// accidentally shared config directories, not against other programs
+/// running as the same user.
+pub struct FileStore {
+    path: PathBuf,
+    cipher: XChaCha20Poly1305,
+    secrets: Mutex<HashMap<String, String>>,
+}
+
+impl FileStore {
+    pub fn open(dir: &Path) -> Result<Self, Error> {
+        let key_path = dir.join(KEY_FILE_NAME);
+
+        let key = if key_path.is_file() {
+            let key = fs::read(&key_path).map_err(Error::Io)?;
+
+            if key.len() != 32 {
+                return Err(Error::Decrypt);
+            }
+
+            *Key::from_slice(&key)
+        } else {
+            let key = XChaCha20Poly1305::generate_key(&mut OsRng);
+            write_private(&key_path, &key)?;
+
+            key
+        };
+
+        let store = Self {
+            path: dir.join(SECRETS_FILE_NAME),
+            cipher: XChaCha20Poly1305::new(&key),
+            secrets: Mutex::new(HashMap::new()),
+        };
+
+        if store.path.is_file() {
+            *store.secrets.lock().unwrap() = store.load()?;
+        }
+
+        Ok(store)
+    }
+    fn load(&self) -> Result<HashMap<String, String>, Error> {
+        let raw = fs::read(&self.path).map_err(Error::Io)?;
+
+        if raw.len() < NONCE_LEN {
+            return Err(Error::Decrypt);
+        }
+
+        let (nonce, ciphertext) = raw.split_at(NONCE_LEN);
+
+        let plaintext = self
+            .cipher
+            .decrypt(XNonce::from_slice(nonce), ciphertext)
+            .map_err(|_| Error::Decrypt)?;
+
+        serde_json::from_slice(&plaintext).map_err(Error::Json)
+    }
+    fn save(&self, secrets: &HashMap<String, String>) -> Result<(), Error> {
+        let plaintext = serde_json::to_vec(secrets).map_err(Error::Json)?;
+
+        let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self
+            .cipher
+            .encrypt(&nonce, plaintext.as_slice())
+            .map_err(|_| Error::Encrypt)?;
+
+        let mut raw = nonce.to_vec();
+        raw.extend_from_slice(&ciphertext);
+
+        write_private(&self.path, &raw)
+    }
+}
+
+impl SecretStore for FileStore {
+    fn get(&self, id: &str) -> Result<Option<String>, Error> {
+        Ok(self.secrets.lock().unwrap().get(id).cloned())
+    }
+    fn set(&self, id: &str, secret: &str) -> Result<(), Error> {
+        let mut secrets = self.secrets.lock().unwrap();
+
+        secrets.insert(id.to_string(), secret.to_string());
+
+        self.save(&secrets)
+    }
+    fn delete(&self, id: &str) -> Result<(), Error> {
+        let mut secrets = self.secrets.lock().unwrap();
+
+        if secrets.remove(id).is_some() {
+            self.save(&secrets)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Keeps secrets in memory only, for running headless or in CI
+#[derive(Default)]
+pub struct MemoryStore {
+    secrets: Mutex<HashMap<String, String>>,
+}
+
+impl SecretStore for MemoryStore {
+    fn get(&self, id: &str) -> Result<Option<String>, Error> {
+        Ok(self.secrets.lock().unwrap().get(id).cloned())
+    }
+    fn set(&self, id: &str, secret: &str) -> Result<(), Error> {
+        self.secrets.lock().unwrap().insert(id.to_string(), secret.to_string());
+
+        Ok(())
+    }
+    fn delete(&self, id: &str) -> Result<(), Error> {
+        self.secrets.lock().unwrap().remove(id);
+
+        Ok(())
+    }
+}
+
+/// Writes a file only readable by the current user
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), Error> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+
+    let mut f = options.open(path).map_err(Error::Io)?;
+
+    std::io::Write::write_all(&mut f, contents).map_err(Error::Io)
+}
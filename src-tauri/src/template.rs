@@ -0,0 +1,126 @@
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashSet;
+
+use crate::runner::instance::{InstanceRequest, InstanceType};
+
+/// Placeholder replaced with the template name
+const TEMPLATE_PLACEHOLDER: &str = "{template}";
+/// Placeholder replaced with the lowest number not used by another instance
+const NUMBER_PLACEHOLDER: &str = "{n}";
+
+/// Saved instance configuration that instances can be created from
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct InstanceTemplate {
+    pub name: String,
+    /// Kept as JSON so types unknown to this console are saved unchanged,
+    /// see `instance_type`
+    #[serde(rename = "type")]
+    pub inst_type: Value,
+    /// Name of created instances, see `instance_name`
+    #[serde(default = "default_name_pattern")]
+    pub name_pattern: String,
+}
+
+pub fn default_name_pattern() -> String {
+    format!("{}-{}", TEMPLATE_PLACEHOLDER, NUMBER_PLACEHOLDER)
+}
+
+impl InstanceTemplate {
+    /// Expands the name pattern, `{template}` becomes the template name
+    /// and `{n}` the lowest number giving a name that isn't `taken`
+    pub fn instance_name(&self, taken: &HashSet<String>) -> String {
+        let name = self.name_pattern.replace(TEMPLATE_PLACEHOLDER, &self.name);
+
+        if !name.contains(NUMBER_PLACEHOLDER) {
+            return name;
+        }
+
+        (1..)
+            .map(|n: u64| name.replace(NUMBER_PLACEHOLDER, &n.to_string()))
+            .find(|name| !taken.contains(name))
+            .unwrap_or(name)
+    }
+    /// Checks the template without decoding its type, so templates of
+    /// types this console doesn't know are accepted
+    pub fn check_structure(&self) -> Result<(), String> {
+        if self.name.trim().is_empty() {
+            return Err("The template name is empty".to_string());
+        }
+
+        if self.instance_name(&HashSet::new()).trim().is_empty() {
+            return Err("The name pattern results in an empty name".to_string());
+        }
+
+        match &self.inst_type {
+            Value::Object(map) if map.len() == 1 => Ok(()),
+            _ => Err("The instance type must be an object with a single type name".to_string()),
+        }
+    }
+    /// Decodes the instance type, fails if a known type is malformed
+    pub fn instance_type(&self) -> Result<InstanceType, serde_json::Error> {
+        serde_json::from_value(self.inst_type.clone())
+    }
+    pub fn to_request(&self, name: String) -> Result<InstanceRequest, serde_json::Error> {
+        Ok(InstanceRequest {
+            name,
+            inst_type: self.instance_type()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn template(inst_type: Value) -> InstanceTemplate {
+        InstanceTemplate {
+            name: String::from("lobby"),
+            inst_type,
+            name_pattern: default_name_pattern(),
+        }
+    }
+
+    #[test]
+    fn unknown_types_round_trip() {
+        let inst_type = json!({ "vm": { "image": "debian", "cpus": 2 } });
+        let template = template(inst_type.clone());
+
+        let saved = serde_json::to_value(&template).unwrap();
+        let loaded: InstanceTemplate = serde_json::from_value(saved).unwrap();
+
+        assert_eq!(loaded.inst_type, inst_type);
+        assert!(matches!(loaded.instance_type(), Ok(InstanceType::Unknown(kind)) if kind == "vm"));
+    }
+
+    #[test]
+    fn structure_check_accepts_unknown_types() {
+        assert!(template(json!({ "vm": { "image": "debian" } })).check_structure().is_ok());
+        assert!(template(json!("vm")).check_structure().is_err());
+        assert!(template(json!({ "vm": {}, "container": {} })).check_structure().is_err());
+
+        let mut unnamed = template(json!({ "container": { "image": "nginx" } }));
+        unnamed.name = String::from(" ");
+        assert!(unnamed.check_structure().is_err());
+
+        let mut empty_pattern = template(json!({ "container": { "image": "nginx" } }));
+        empty_pattern.name_pattern = String::from(" ");
+        assert!(empty_pattern.check_structure().is_err());
+    }
+
+    #[test]
+    fn malformed_known_types_fail_to_decode() {
+        let template = template(json!({ "container": { "env": {} } }));
+
+        assert!(template.to_request(String::from("lobby-1")).is_err());
+    }
+
+    #[test]
+    fn instance_name_skips_taken_numbers() {
+        let template = template(json!({ "container": { "image": "nginx" } }));
+        let taken = HashSet::from([String::from("lobby-1"), String::from("lobby-2")]);
+
+        assert_eq!(template.instance_name(&taken), "lobby-3");
+    }
+}
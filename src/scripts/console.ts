@@ -0,0 +1,69 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+export interface ConsoleLine {
+  stream: "stdout" | "stderr";
+  text: string;
+  time: number | null;
+}
+
+export interface ConsoleEvent {
+  runner: string;
+  instance: string;
+  line: ConsoleLine;
+}
+
+/**
+ * Starts streaming the console of an instance.
+ *
+ * @param runner - The identifier of the runner.
+ * @param instance - The identifier of the instance.
+ * @returns A Promise that resolves to the console history of the instance.
+ */
+export async function openConsole(runner: string, instance: string): Promise<ConsoleLine[]> {
+  return await invoke("console_open", { runner, instance });
+}
+
+/**
+ * Stops streaming the console of an instance.
+ *
+ * @param runner - The identifier of the runner.
+ * @param instance - The identifier of the instance.
+ */
+export async function closeConsole(runner: string, instance: string) {
+  await invoke("console_close", { runner, instance });
+}
+
+/**
+ * Sends a line of input to the stdin of an instance.
+ *
+ * @param runner - The identifier of the runner.
+ * @param instance - The identifier of the instance.
+ * @param input - The line to send.
+ */
+export async function sendInstanceInput(runner: string, instance: string, input: string) {
+  await invoke("send_instance_input", { runner, instance, input });
+}
+
+/**
+ * Retrieves the commands previously sent to an instance.
+ *
+ * @param runner - The identifier of the runner.
+ * @param instance - The identifier of the instance.
+ * @returns A Promise that resolves to the commands, oldest first.
+ */
+export async function instanceInputHistory(runner: string, instance: string): Promise<string[]> {
+  return await invoke("instance_input_history", { runner, instance });
+}
+
+/**
+ * Sets up a listener for console output of all open consoles.
+ *
+ * @param onLine - Callback function invoked for every received line.
+ * @returns A Promise that resolves to a function removing the listener
+ */
+export async function consoleListener(onLine: (event: ConsoleEvent) => void) {
+  return await listen<ConsoleEvent>("console", (event) => {
+    onLine(event.payload);
+  });
+}
@@ -1,6 +1,22 @@
 import { listen } from "@tauri-apps/api/event";
 
-import type { Runner } from "./runner";
+import type { Instance } from "./instance";
+import type { ConnectionStatus, Runner, RunnerHealth } from "./runner";
+
+export type RunnerChange =
+  | { type: "runner-updated", runner: string, details: Runner }
+  | { type: "runner-removed", runner: string }
+  | {
+    type: "status-changed",
+    runner: string,
+    status: ConnectionStatus,
+    latency: number | null,
+    failures: number,
+    lastSeen: number | null,
+  }
+  | { type: "health-changed", runner: string, health: RunnerHealth | null }
+  | { type: "instance-upserted", runner: string, id: string, instance: Instance }
+  | { type: "instance-removed", runner: string, id: string };
 
 /**
  * Sets up a listener for runner changes and transforms the received payload into a Map structure.
@@ -26,3 +42,59 @@ export async function runnerListener(onRunnerChange: (runners: Map<string, Runne
     onRunnerChange(runnersMap);
   });
 }
+
+/**
+ * Sets up a listener for changes to single runners and their instances.
+ * 
+ * The full runner list is still sent through `runnerListener` on startup
+ * and whenever changes were missed.
+ * 
+ * @param onChange - Callback function invoked for every change.
+ * @returns A Promise that resolves to a function removing the listener
+ */
+export async function runnerChangeListener(onChange: (change: RunnerChange) => void) {
+  return await listen<RunnerChange>("runner-change", (event) => {
+    onChange(event.payload);
+  });
+}
+
+/**
+ * Applies a change to a map of runners in place.
+ * 
+ * @param runners - Map of runner IDs to runners
+ * @param change - The change to apply
+ */
+export function applyRunnerChange(runners: Map<string, Runner>, change: RunnerChange) {
+  switch (change.type) {
+    case "runner-updated":
+      change.details.instances = new Map(Object.entries(change.details.instances));
+      runners.set(change.runner, change.details);
+      return;
+    case "runner-removed":
+      runners.delete(change.runner);
+      return;
+  }
+
+  const runner = runners.get(change.runner);
+  if (!runner) {
+    return;
+  }
+
+  switch (change.type) {
+    case "status-changed":
+      runner.status = change.status;
+      runner.latency = change.latency;
+      runner.failures = change.failures;
+      runner.lastSeen = change.lastSeen;
+      break;
+    case "health-changed":
+      runner.health = change.health;
+      break;
+    case "instance-upserted":
+      runner.instances.set(change.id, change.instance);
+      break;
+    case "instance-removed":
+      runner.instances.delete(change.id);
+      break;
+  }
+}
@@ -1,15 +1,43 @@
 import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
 
 export interface Instance {
   name: string;
   type: InstanceType;
   status: InstanceStatus;
+  /** JSON as sent by the runner */
+  raw: unknown;
+  /** Set if the console couldn't fully understand the instance */
+  decodeError: string | null;
 }
 
-export type InstanceType = {
-  volkanic: {
-    source: VolkanicSource
+export type InstanceType =
+  | {
+    volkanic: {
+      source: VolkanicSource
+    }
   }
+  | {
+    container: {
+      image: string,
+      env: Record<string, string>,
+      ports: PortMapping[],
+      volumes: VolumeMount[],
+    }
+  }
+  /** A type this console doesn't know, holds the type name */
+  | { unknown: string };
+
+export interface PortMapping {
+  host: number;
+  container: number;
+  protocol?: "tcp" | "udp";
+}
+
+export interface VolumeMount {
+  source: string;
+  target: string;
+  readOnly?: boolean;
 }
 
 export type InstanceStatus =
@@ -18,11 +46,36 @@ export type InstanceStatus =
   | { creating: number }
   | "deleting" 
   | "starting"
-  | "stopping";
+  | "stopping"
+  | { unknown: string };
 
 export type VolkanicSource =
   | { url: string }
-  | { base64: string };
+  | { base64: string }
+  | { upload: string }
+  | { unknown: string };
+
+export interface ValidationIssue {
+  severity: "error" | "warning";
+  message: string;
+}
+
+export interface ValidationReport {
+  issues: ValidationIssue[];
+  /** Size of the source in bytes, if known */
+  size: number | null;
+  format: "zip" | "gzip" | "tar" | null;
+  /** Whether the source URL was reachable, null if it wasn't probed */
+  reachable: boolean | null;
+}
+
+export interface UploadProgress {
+  runner: string;
+  /** Path of the file being uploaded */
+  path: string;
+  sent: number;
+  total: number;
+}
 
   export interface InstanceRequest {
     name: string;
@@ -43,6 +96,37 @@ export async function newInstance(runner: string, instance: InstanceRequest) {
   await invoke("new_instance", { runner, instance });
 }
 
+/**
+ * Checks an instance request without sending it to the runner.
+ * @param runner - The identifier of the runner, its TLS settings are used for probing.
+ * @param instance - The instance request to check.
+ * @param probe - Whether to request source URLs to check if they are reachable.
+ * @returns A Promise that resolves to the validation report.
+ */
+export async function validateInstance(runner: string, instance: InstanceRequest, probe = false): Promise<ValidationReport> {
+  return await invoke("validate_instance", { runner, instance, probe });
+}
+
+/**
+ * Returns a readable name of an instance type, including types unknown to this console.
+ */
+export function instanceTypeName(type: InstanceType): string {
+  if ("volkanic" in type) {
+    return "Volkanic";
+  } else if ("container" in type) {
+    return `Container (${type.container.image})`;
+  } else {
+    return `Unknown (${type.unknown})`;
+  }
+}
+
+/**
+ * Returns true if a validation report has no errors.
+ */
+export function isValid(report: ValidationReport) {
+  return !report.issues.some((issue) => issue.severity === "error");
+}
+
 export async function startInstance(runner: string, instance: string) {
   await invoke("start_instance", { runner, instance });
 }
@@ -50,3 +134,34 @@ export async function startInstance(runner: string, instance: string) {
 export async function stopInstance(runner: string, instance: string) {
   await invoke("stop_instance", { runner, instance });
 }
+
+/**
+ * Lets the user pick a local file to create an instance from.
+ * @returns A Promise that resolves to the path, or null if cancelled.
+ */
+export async function pickUploadFile(): Promise<string | null> {
+  return await invoke("pick_upload_file");
+}
+
+/**
+ * Uploads a local file to a runner and creates a Volkanic instance from it.
+ * @param runner - The identifier of the runner.
+ * @param name - The name of the new instance.
+ * @param path - The path of the file, see `pickUploadFile`.
+ * @returns A Promise that resolves once the instance was created.
+ */
+export async function newInstanceUpload(runner: string, name: string, path: string) {
+  await invoke("new_instance_upload", { runner, name, path });
+}
+
+/**
+ * Sets up a listener for the progress of file uploads.
+ *
+ * @param onProgress - Callback function invoked after every uploaded chunk.
+ * @returns A Promise that resolves to a function removing the listener
+ */
+export async function uploadProgressListener(onProgress: (progress: UploadProgress) => void) {
+  return await listen<UploadProgress>("upload-progress", (event) => {
+    onProgress(event.payload);
+  });
+}
@@ -5,19 +5,105 @@ import type { Instance } from "./instance";
 export interface Runner {
   name: string;
   url: string;
-  connected: boolean;
+  status: ConnectionStatus;
+  latency: number | null;
+  failures: number;
+  lastSeen: number | null;
+  health: RunnerHealth | null;
+  compatibility: Compatibility;
+  protocol: number | null;
+  capabilities: Capability[];
+  instanceTypes: string[];
   instances: Map<string, Instance>
 }
 
+export type ConnectionStatus = "online" | "degraded" | "offline";
+
+export type Compatibility = "unknown" | "compatible" | "runnerNewer" | "runnerOlder";
+
+export type Capability = "instances" | "events" | "console" | "input" | "stats" | "backups" | "files" | "uploads";
+
+export interface RunnerHealth {
+  version: string;
+  uptime: number | null;
+  load: number[];
+  cpus: number | null;
+  memoryUsed: number | null;
+  memoryTotal: number | null;
+  diskUsed: number | null;
+  diskTotal: number | null;
+  instancesTotal: number | null;
+  instancesRunning: number | null;
+}
+
+export type RunnerAuth =
+  | "none"
+  | { token: { token: string } }
+  | { login: { username: string, password: string } };
+
+export interface TlsSettings {
+  caBundle?: string;
+  pinnedFingerprint?: string;
+  clientCert?: string;
+  clientKey?: string;
+  acceptInvalidCerts?: boolean;
+}
+
+export interface BackoffPolicy {
+  initialMs?: number;
+  maxMs?: number;
+  multiplier?: number;
+  jitter?: number;
+}
+
 /**
  * Creates a new runner with the specified name and URL.
  * 
  * @param name - The name of the runner to create
  * @param url - The URL associated with the runner
+ * @param auth - Credentials for runners requiring authentication
+ * @param tls - Custom TLS trust and client certificate settings
+ * @param backoff - Reconnection delays, the defaults are used if omitted
  * @returns Promise that resolves when the runner is created
  */
-export async function newRunner(name: string, url: string) {
-  await invoke("runner_new", { name, url });
+export async function newRunner(name: string, url: string, auth?: RunnerAuth, tls?: TlsSettings, backoff?: BackoffPolicy) {
+  await invoke("runner_new", { name, url, auth, tls, backoff });
+}
+
+/**
+ * Removes a runner and stops all communication with it.
+ * 
+ * @param id - The identifier of the runner to remove
+ * @returns Promise that resolves when the runner is removed
+ */
+export async function removeRunner(id: string) {
+  await invoke("runner_remove", { id });
+}
+
+/**
+ * Changes the name and URL of an existing runner. The runner reconnects
+ * if the URL was changed.
+ * 
+ * @param id - The identifier of the runner to update
+ * @param name - The new name of the runner
+ * @param url - The new URL of the runner
+ * @param auth - New credentials, the existing ones are kept if omitted
+ * @param tls - New TLS settings, the existing ones are kept if omitted
+ * @param backoff - New reconnection delays, the existing ones are kept if omitted
+ * @returns Promise that resolves when the runner is updated
+ */
+export async function updateRunner(id: string, name: string, url: string, auth?: RunnerAuth, tls?: TlsSettings, backoff?: BackoffPolicy) {
+  await invoke("runner_update", { id, name, url, auth, tls, backoff });
+}
+
+/**
+ * Skips the pending reconnection delays of a runner and retries right away.
+ * 
+ * @param id - The identifier of the runner to reconnect
+ * @returns Promise that resolves once the reconnect was requested
+ */
+export async function reconnectRunner(id: string) {
+  await invoke("runner_reconnect", { id });
 }
 
 /**
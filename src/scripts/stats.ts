@@ -0,0 +1,61 @@
+import { invoke } from "@tauri-apps/api/core";
+import { listen } from "@tauri-apps/api/event";
+
+export interface StatsSample {
+  time: number;
+  cpu: number;
+  memory: number;
+  memoryLimit: number | null;
+  uptime: number;
+}
+
+export interface StatsEvent {
+  runner: string;
+  instance: string;
+  sample: StatsSample;
+}
+
+/**
+ * Retrieves the recorded resource usage of an instance.
+ *
+ * @param runner - The identifier of the runner.
+ * @param instance - The identifier of the instance.
+ * @returns A Promise that resolves to the samples, oldest first.
+ */
+export async function instanceStats(runner: string, instance: string): Promise<StatsSample[]> {
+  return await invoke("instance_stats", { runner, instance });
+}
+
+/**
+ * Starts pulling the resource usage of an instance on an interval, for
+ * runners that don't push it. Samples are sent to `statsListener`.
+ *
+ * @param runner - The identifier of the runner.
+ * @param instance - The identifier of the instance.
+ * @returns A Promise that resolves to the samples recorded so far, oldest first.
+ */
+export async function watchStats(runner: string, instance: string): Promise<StatsSample[]> {
+  return await invoke("stats_watch", { runner, instance });
+}
+
+/**
+ * Stops pulling the resource usage of an instance.
+ *
+ * @param runner - The identifier of the runner.
+ * @param instance - The identifier of the instance.
+ */
+export async function unwatchStats(runner: string, instance: string) {
+  await invoke("stats_unwatch", { runner, instance });
+}
+
+/**
+ * Sets up a listener for resource usage of all instances.
+ *
+ * @param onSample - Callback function invoked for every received sample.
+ * @returns A Promise that resolves to a function removing the listener
+ */
+export async function statsListener(onSample: (event: StatsEvent) => void) {
+  return await listen<StatsEvent>("stats", (event) => {
+    onSample(event.payload);
+  });
+}
@@ -0,0 +1,74 @@
+import { invoke } from "@tauri-apps/api/core";
+import type { InstanceType } from "./instance";
+
+export interface Template {
+  id: string;
+  name: string;
+  type: InstanceType;
+  /**
+   * Name of created instances. `{template}` is replaced with the template
+   * name and `{n}` with the lowest free number.
+   */
+  namePattern: string;
+}
+
+export interface TemplateRequest {
+  /** Not set for templates that weren't saved yet */
+  id: string | null;
+  name: string;
+  type: InstanceType;
+  /** Defaults to `{template}-{n}` */
+  namePattern: string | null;
+}
+
+/**
+ * Retrieves all saved templates, sorted by name.
+ * @returns A Promise that resolves to the templates.
+ */
+export async function templateList(): Promise<Template[]> {
+  return await invoke("template_list");
+}
+
+/**
+ * Saves a template, replacing the existing one if it has an ID.
+ * @param template - The template to save.
+ * @returns A Promise that resolves to the ID of the template.
+ */
+export async function templateSave(template: TemplateRequest): Promise<string> {
+  return await invoke("template_save", { template });
+}
+
+/**
+ * Deletes a saved template.
+ * @param id - The identifier of the template.
+ */
+export async function templateDelete(id: string) {
+  await invoke("template_delete", { id });
+}
+
+/**
+ * Creates an instance from a saved template.
+ * @param runner - The identifier of the runner.
+ * @param template - The identifier of the template.
+ * @param name - The name of the instance, generated from the template if omitted.
+ */
+export async function instanceFromTemplate(runner: string, template: string, name?: string) {
+  await invoke("instance_from_template", { runner, template, name: name ?? null });
+}
+
+/**
+ * Lets the user choose a JSON file and writes templates to it.
+ * @param ids - The identifiers of the templates to export, all if omitted.
+ * @returns A Promise that resolves to false if the user cancelled.
+ */
+export async function templateExport(ids?: string[]): Promise<boolean> {
+  return await invoke("template_export", { ids: ids ?? null });
+}
+
+/**
+ * Lets the user choose a JSON file and adds the templates in it.
+ * @returns A Promise that resolves to the imported templates.
+ */
+export async function templateImport(): Promise<Template[]> {
+  return await invoke("template_import");
+}